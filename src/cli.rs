@@ -32,6 +32,18 @@ pub enum PermissionsMode {
     Fixed,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum, PartialEq)]
+pub enum Encoding {
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[value(name = "utf-16be")]
+    Utf16Be,
+    #[value(name = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
 #[derive(Debug, Clone, clap::ValueEnum, PartialEq, Copy)]
 #[clap(rename_all = "kebab-case")]
 pub enum ValidationMode {
@@ -40,6 +52,20 @@ pub enum ValidationMode {
     None,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum, PartialEq, Copy)]
+#[clap(rename_all = "kebab-case")]
+pub enum RegexEngine {
+    Regex,
+    Fancy,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum, PartialEq, Copy)]
+#[clap(rename_all = "kebab-case")]
+pub enum Whitespace {
+    Exact,
+    Flexible,
+}
+
 #[derive(Debug, Clone, clap::ValueEnum, PartialEq)]
 pub enum OutputFormat {
     Diff,
@@ -124,10 +150,71 @@ pub struct DefaultArgs {
     #[arg(long = "rg-json", conflicts_with_all = ["stdin_paths", "files0", "stdin_text", "files_arg"], help_heading = "Input Options")]
     pub rg_json: bool,
 
+    /// Apply a unified diff (`-` reads it from stdin). Each hunk's context is verified.
+    #[arg(long = "patch", value_name = "FILE", conflicts_with_all = ["stdin_paths", "files0", "stdin_text", "rg_json", "files_arg"], help_heading = "Input Options")]
+    pub patch: Option<PathBuf>,
+
+    /// Let patch hunks ignore up to N leading and trailing context lines.
+    #[arg(
+        long = "fuzz",
+        value_name = "N",
+        default_value_t = 0,
+        requires = "patch",
+        help_heading = "Input Options"
+    )]
+    pub fuzz: usize,
+
+    /// Edit every file under DIR, honoring .gitignore (requires the `walk` feature).
+    #[arg(long = "walk", value_name = "DIR", conflicts_with_all = ["stdin_paths", "files0", "stdin_text", "rg_json", "patch", "files_arg"], help_heading = "Input Options")]
+    pub walk: Option<PathBuf>,
+
+    /// Include hidden files and directories when walking.
+    #[arg(long = "hidden", requires = "walk", help_heading = "Input Options")]
+    pub hidden: bool,
+
+    /// Do not honor .gitignore, .ignore or git exclude files when walking.
+    #[arg(long = "no-ignore", requires = "walk", help_heading = "Input Options")]
+    pub no_ignore: bool,
+
+    /// Descend at most N directories below the walk root.
+    #[arg(
+        long = "max-depth",
+        value_name = "N",
+        requires = "walk",
+        help_heading = "Input Options"
+    )]
+    pub max_depth: Option<usize>,
+
     /// Force positional arguments to be treated as files even if stdin is present.
     #[arg(long = "files", conflicts_with_all = ["stdin_paths", "files0", "stdin_text", "rg_json"], visible_alias = "files-arg", help_heading = "Input Options")]
     pub files_arg: bool,
 
+    /// Add a FIND=REPLACE pair (repeatable). All positional arguments are then files.
+    #[arg(
+        short = 'e',
+        long = "expression",
+        value_name = "FIND=REPLACE",
+        help_heading = "Input Options"
+    )]
+    pub expression: Vec<String>,
+
+    /// Add a FIND REPLACE pair (repeatable). All positional arguments are then files.
+    #[arg(
+        long = "pair",
+        num_args = 2,
+        value_names = ["FIND", "REPLACE"],
+        help_heading = "Input Options"
+    )]
+    pub pair: Vec<String>,
+
+    /// Read FIND/REPLACE pairs from a TSV file, or a JSON file ending in `.json`.
+    #[arg(
+        long = "pairs-file",
+        value_name = "FILE",
+        help_heading = "Input Options"
+    )]
+    pub pairs_file: Option<PathBuf>,
+
     // ========================================================================
     // Match options
     // ========================================================================
@@ -172,6 +259,34 @@ pub struct DefaultArgs {
     #[arg(long = "no-unicode", help_heading = "Match Options")]
     pub no_unicode: bool,
 
+    /// Regex engine for FIND; `fancy` adds lookaround and backreferences (needs the `fancy` feature).
+    #[arg(
+        long = "engine",
+        value_enum,
+        requires = "regex",
+        help_heading = "Match Options"
+    )]
+    pub engine: Option<RegexEngine>,
+
+    /// Backtracking steps the fancy engine may take per search before failing.
+    #[arg(
+        long = "backtrack-limit",
+        value_name = "N",
+        requires = "engine",
+        help_heading = "Match Options"
+    )]
+    pub backtrack_limit: Option<usize>,
+
+    /// With `flexible`, any whitespace run in FIND matches any whitespace run, and REPLACE is
+    /// reindented to the matched text. FIND is taken literally.
+    #[arg(
+        long = "whitespace",
+        value_enum,
+        conflicts_with_all = ["regex", "preserve_case"],
+        help_heading = "Match Options"
+    )]
+    pub whitespace: Option<Whitespace>,
+
     /// Maximum replacements per file.
     #[arg(
         long = "limit",
@@ -189,6 +304,32 @@ pub struct DefaultArgs {
     )]
     pub range: Option<String>,
 
+    /// Only replace the Nth match per file (N, -N from the end, START:END, START: or :END).
+    #[arg(
+        long = "nth",
+        value_name = "N|START:END",
+        allow_hyphen_values = true,
+        conflicts_with = "preserve_case",
+        help_heading = "Scope Options"
+    )]
+    pub nth: Option<String>,
+
+    /// Also replace the snake, SCREAMING, kebab, camel and Pascal case spellings of FIND,
+    /// each with REPLACE in the same convention. FIND is taken literally.
+    #[arg(
+        long = "preserve-case",
+        conflicts_with_all = [
+            "regex",
+            "expand",
+            "ignore_case",
+            "smart_case",
+            "multiline",
+            "dot_matches_newline"
+        ],
+        help_heading = "Match Options"
+    )]
+    pub preserve_case: bool,
+
     /// Enable regex capture expansion (e.g. $1, $name).
     #[arg(long = "expand", help_heading = "Match Options")]
     pub expand: bool,
@@ -283,6 +424,19 @@ pub struct DefaultArgs {
     )]
     pub permissions: Option<PermissionsMode>,
 
+    /// File encoding. Detected from the byte order mark (else UTF-8) if not given.
+    #[arg(
+        long = "encoding",
+        value_enum,
+        global = true,
+        help_heading = "Configuration"
+    )]
+    pub encoding: Option<Encoding>,
+
+    /// Process files line by line with bounded memory (line-bounded operations only).
+    #[arg(long = "stream", global = true, help_heading = "Configuration")]
+    pub stream: bool,
+
     /// Fixed permissions mode (e.g. 755), used if --permissions=fixed.
     #[arg(
         long = "mode",
@@ -0,0 +1,185 @@
+use crate::error::{Error, Result};
+use crate::model::Encoding;
+use std::borrow::Cow;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// How a file's text is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEncoding {
+    pub encoding: Encoding,
+    /// The file starts with a byte order mark, which is kept on write.
+    pub bom: bool,
+}
+
+impl TextEncoding {
+    /// Detect the encoding of `bytes` from its BOM.
+    /// A `declared` encoding always wins; its BOM is still recognised and kept.
+    pub fn detect(bytes: &[u8], declared: Option<Encoding>) -> Self {
+        let sniffed = if bytes.starts_with(UTF8_BOM) {
+            Some(Encoding::Utf8)
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            Some(Encoding::Utf16Le)
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Some(Encoding::Utf16Be)
+        } else {
+            None
+        };
+
+        match declared {
+            Some(encoding) => Self {
+                encoding,
+                bom: sniffed == Some(encoding),
+            },
+            None => Self {
+                encoding: sniffed.unwrap_or_default(),
+                bom: sniffed.is_some(),
+            },
+        }
+    }
+
+    fn bom_bytes(&self) -> &'static [u8] {
+        match (self.bom, self.encoding) {
+            (false, _) | (true, Encoding::Latin1) => b"",
+            (true, Encoding::Utf8) => UTF8_BOM,
+            (true, Encoding::Utf16Le) => UTF16LE_BOM,
+            (true, Encoding::Utf16Be) => UTF16BE_BOM,
+        }
+    }
+
+    /// Convert file content to UTF-8, dropping the BOM.
+    /// UTF-8 content is borrowed as-is, including any invalid sequences.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let body = &bytes[self.bom_bytes().len()..];
+        match self.encoding {
+            Encoding::Utf8 => Ok(Cow::Borrowed(body)),
+            Encoding::Utf16Le => decode_utf16(body, u16::from_le_bytes).map(Cow::Owned),
+            Encoding::Utf16Be => decode_utf16(body, u16::from_be_bytes).map(Cow::Owned),
+            Encoding::Latin1 => Ok(Cow::Owned(
+                body.iter()
+                    .map(|&b| b as char)
+                    .collect::<String>()
+                    .into_bytes(),
+            )),
+        }
+    }
+
+    /// Convert UTF-8 text back to this encoding, restoring the BOM.
+    pub fn encode(&self, text: Vec<u8>) -> Result<Vec<u8>> {
+        let mut out = self.bom_bytes().to_vec();
+        if self.encoding == Encoding::Utf8 {
+            if out.is_empty() {
+                return Ok(text);
+            }
+            out.extend_from_slice(&text);
+            return Ok(out);
+        }
+
+        let text = std::str::from_utf8(&text).map_err(|_| {
+            Error::Encoding(format!(
+                "content is not valid UTF-8 and cannot be written as {}",
+                self.encoding.name()
+            ))
+        })?;
+        match self.encoding {
+            Encoding::Utf16Le => out.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => out.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::Latin1 => {
+                for c in text.chars() {
+                    let byte = u8::try_from(u32::from(c)).map_err(|_| {
+                        Error::Encoding(format!("'{}' cannot be represented in latin1", c))
+                    })?;
+                    out.push(byte);
+                }
+            }
+            Encoding::Utf8 => unreachable!(),
+        }
+        Ok(out)
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<Vec<u8>> {
+    if bytes.len() % 2 != 0 {
+        return Err(Error::Encoding(
+            "odd number of bytes in UTF-16 content".into(),
+        ));
+    }
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .collect::<std::result::Result<String, _>>()
+        .map(String::into_bytes)
+        .map_err(|e| Error::Encoding(format!("invalid UTF-16 content: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let mut out = if bom { UTF16LE_BOM.to_vec() } else { vec![] };
+        out.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        out
+    }
+
+    #[test]
+    fn detects_boms() {
+        let detect = |bytes: &[u8]| TextEncoding::detect(bytes, None);
+        assert_eq!(detect(b"\xEF\xBB\xBFx").encoding, Encoding::Utf8);
+        assert!(detect(b"\xEF\xBB\xBFx").bom);
+        assert_eq!(detect(b"\xFF\xFEx\x00").encoding, Encoding::Utf16Le);
+        assert_eq!(detect(b"\xFE\xFF\x00x").encoding, Encoding::Utf16Be);
+        assert_eq!(
+            detect(b"plain"),
+            TextEncoding {
+                encoding: Encoding::Utf8,
+                bom: false
+            }
+        );
+    }
+
+    #[test]
+    fn declared_encoding_wins() {
+        let enc = TextEncoding::detect(b"\xE9t\xE9", Some(Encoding::Latin1));
+        assert_eq!(enc.encoding, Encoding::Latin1);
+        assert!(!enc.bom);
+        assert_eq!(enc.decode(b"\xE9t\xE9").unwrap(), "été".as_bytes());
+
+        let enc = TextEncoding::detect(&utf16le("a", true), Some(Encoding::Utf16Le));
+        assert!(enc.bom);
+    }
+
+    #[test]
+    fn utf16_round_trip_keeps_bom() {
+        let bytes = utf16le("héllo\r\n", true);
+        let enc = TextEncoding::detect(&bytes, None);
+        let text = enc.decode(&bytes).unwrap().into_owned();
+        assert_eq!(text, "héllo\r\n".as_bytes());
+        assert_eq!(enc.encode(text).unwrap(), bytes);
+    }
+
+    #[test]
+    fn utf8_bom_round_trip() {
+        let enc = TextEncoding::detect(b"\xEF\xBB\xBFabc", None);
+        let text = enc.decode(b"\xEF\xBB\xBFabc").unwrap().into_owned();
+        assert_eq!(text, b"abc");
+        assert_eq!(enc.encode(text).unwrap(), b"\xEF\xBB\xBFabc");
+    }
+
+    #[test]
+    fn rejects_invalid_content() {
+        let enc = TextEncoding {
+            encoding: Encoding::Utf16Le,
+            bom: false,
+        };
+        assert_eq!(enc.decode(&[0x61]).unwrap_err().code(), "E_ENCODING");
+
+        let enc = TextEncoding {
+            encoding: Encoding::Latin1,
+            bom: false,
+        };
+        let err = enc.encode("€".as_bytes().to_vec()).unwrap_err();
+        assert_eq!(err.code(), "E_ENCODING");
+    }
+}
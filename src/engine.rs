@@ -1,33 +1,55 @@
+use crate::encoding::TextEncoding;
+use crate::eol::{self, EolStats};
 use crate::error::{Error, Result};
+use crate::events::OperationStats;
 use crate::input::InputItem;
 use crate::model::ReplacementRange;
-use crate::model::{BinaryFileMode, Operation, Pipeline, Symlinks, Transaction};
+use crate::model::{BinaryFileMode, Encoding, FileOperation, Pipeline, Symlinks, Transaction};
+use crate::patch::FilePatch;
+use crate::plan::{Plan, Step};
 use crate::policy::{enforce_pre_execution, PolicyEnforcer};
-use crate::replacer::Replacer;
-use crate::reporter::{FileResult, Report};
+use crate::replacer::TemplateVars;
+use crate::reporter::{FileChange, FileResult, Report};
+use crate::source::Source;
+use crate::stream::{check_streamable, stream_lines};
 use crate::transaction::TransactionManager;
-use crate::write::{stage_file, write_file, StagedEntry, WriteOptions};
+use crate::write::{
+    stage_file, stage_file_with, stage_move, stage_new_file, write_file, StagedEntry, WriteOptions,
+};
 use globset::{Glob, GlobSet, GlobSetBuilder};
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 use similar::{ChangeTag, TextDiff};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::{Component, Path, PathBuf};
 use std::time::Instant;
 
+/// Read and write buffer size for `--stream`.
+const STREAM_BUFFER_SIZE: usize = 256 * 1024;
+
 /// Execute a pipeline and produce a report.
 pub fn execute(mut pipeline: Pipeline, inputs: Vec<InputItem>) -> Result<Report> {
     let start_time = Instant::now();
 
     // validate semantic constraints
-    if inputs.is_empty() {
+    if inputs.is_empty() && pipeline.file_operations.is_empty() {
         return Err(Error::Validation("No input sources specified".into()));
     }
-    if pipeline.operations.is_empty() {
+    let has_patches = inputs.iter().any(|i| matches!(i, InputItem::Patch { .. }));
+    if pipeline.operations.is_empty() && pipeline.file_operations.is_empty() && !has_patches {
         return Err(Error::Validation("No operations specified".into()));
     }
 
+    // Compile once up front: invalid operations fail before any file is touched
+    let plan = Plan::compile(&pipeline.operations)?;
+    if pipeline.stream {
+        check_streamable(&pipeline.operations)?;
+    }
+
     // Build glob sets
     let (include_set, exclude_set) =
         build_glob_sets(&pipeline.glob_include, &pipeline.glob_exclude)?;
@@ -45,13 +67,16 @@ pub fn execute(mut pipeline: Pipeline, inputs: Vec<InputItem>) -> Result<Report>
 
     let cwd = env::current_dir()
         .map_err(|e| Error::Validation(format!("Failed to get current directory: {}", e)))?;
+    check_file_operation_targets(&pipeline.file_operations, &inputs, &cwd)?;
 
     // Define the processing function (closure)
-    let process_item = |input: InputItem| -> (FileResult, Option<StagedEntry>) {
+    // Patches yield one result per rejected hunk, so items map to a list of results
+    let process_item = |input: InputItem| -> Vec<(FileResult, Option<StagedEntry>)> {
         // Check globs first
         let path_for_glob = match &input {
             InputItem::Path(p) => Some(p.as_path()),
             InputItem::RipgrepMatch { path, .. } => Some(path.as_path()),
+            InputItem::Patch { patch, .. } => Some(patch.target()),
             InputItem::StdinText(_) => None,
         };
 
@@ -60,7 +85,7 @@ pub fn execute(mut pipeline: Pipeline, inputs: Vec<InputItem>) -> Result<Report>
             if let Some(ref set) = include_set {
                 if !set.is_match(&normalized) {
                     // Report skipped (glob include mismatch)
-                    return (
+                    return vec![(
                         FileResult {
                             path: p.to_path_buf(),
                             modified: false,
@@ -72,15 +97,16 @@ pub fn execute(mut pipeline: Pipeline, inputs: Vec<InputItem>) -> Result<Report>
                             diff_is_binary: false,
                             generated_content: None,
                             is_virtual: false,
+                            ..Default::default()
                         },
                         None,
-                    );
+                    )];
                 }
             }
             if let Some(ref set) = exclude_set {
                 if set.is_match(&normalized) {
                     // Report skipped (glob exclude)
-                    return (
+                    return vec![(
                         FileResult {
                             path: p.to_path_buf(),
                             modified: false,
@@ -92,57 +118,76 @@ pub fn execute(mut pipeline: Pipeline, inputs: Vec<InputItem>) -> Result<Report>
                             diff_is_binary: false,
                             generated_content: None,
                             is_virtual: false,
+                            ..Default::default()
                         },
                         None,
-                    );
+                    )];
                 }
             }
         }
 
         match input {
+            InputItem::Path(path_buf) if pipeline.stream => {
+                vec![process_file_streaming(
+                    &path_buf, &plan, &pipeline, &enforcer,
+                )]
+            }
             InputItem::Path(path_buf) => {
                 let path_str = path_buf.to_string_lossy().into_owned();
-                process_file(&path_str, &pipeline.operations, &pipeline, None, &enforcer)
+                vec![process_file(&path_str, &plan, &pipeline, None, &enforcer)]
             }
             InputItem::RipgrepMatch { path, matches } => {
                 let path_str = path.to_string_lossy().into_owned();
-                process_file(
+                vec![process_file(
                     &path_str,
-                    &pipeline.operations,
+                    &plan,
                     &pipeline,
                     Some(&matches),
                     &enforcer,
-                )
+                )]
             }
             InputItem::StdinText(text) => {
-                let result = process_text(text, &pipeline.operations, &pipeline);
-                (result, None)
+                let result = process_text(text, &plan, &pipeline);
+                vec![(result, None)]
             }
+            InputItem::Patch { patch, fuzz } => process_patch(&patch, fuzz, &pipeline, &enforcer),
         }
     };
 
-    // Execute in parallel or serial
+    // Execute in parallel or serial. `${__global}` numbers matches in input order,
+    // which only a serial run preserves.
     #[cfg(feature = "parallel")]
-    let results: Vec<(FileResult, Option<StagedEntry>)> =
-        inputs.into_par_iter().map(process_item).collect();
+    let results: Vec<(FileResult, Option<StagedEntry>)> = if plan.uses_global() {
+        inputs.into_iter().flat_map(&process_item).collect()
+    } else {
+        inputs
+            .into_par_iter()
+            .flat_map_iter(&process_item)
+            .collect()
+    };
 
     #[cfg(not(feature = "parallel"))]
     let results: Vec<(FileResult, Option<StagedEntry>)> =
-        inputs.into_iter().map(process_item).collect();
+        inputs.into_iter().flat_map(process_item).collect();
 
     // Aggregate results
+    let mut aborted = false;
     for (result, staged) in results {
-        let has_error = result.error.is_some();
-        report.add_result(result);
-
-        if let Some(s) = staged {
-            if let Some(manager) = &mut tm {
-                manager.stage(s);
-            }
+        if record_result(&mut report, &mut tm, result, staged) {
+            aborted = true;
+            break;
         }
+    }
 
-        if has_error {
-            break;
+    // File-level operations run after content edits, in manifest order
+    if !aborted {
+        let mut planned = HashMap::new();
+        for op in &pipeline.file_operations {
+            let (result, staged) =
+                process_file_operation(op, &pipeline, &enforcer, &cwd, &mut planned);
+            if record_result(&mut report, &mut tm, result, staged) {
+                break;
+            }
         }
     }
 
@@ -166,6 +211,25 @@ pub fn execute(mut pipeline: Pipeline, inputs: Vec<InputItem>) -> Result<Report>
     Ok(report)
 }
 
+/// Add a result to the report and stage its write. Returns true if the result is an error.
+fn record_result(
+    report: &mut Report,
+    tm: &mut Option<TransactionManager>,
+    result: FileResult,
+    staged: Option<StagedEntry>,
+) -> bool {
+    let has_error = result.error.is_some();
+    report.add_result(result);
+
+    if let Some(s) = staged {
+        if let Some(manager) = tm {
+            manager.stage(s);
+        }
+    }
+
+    has_error
+}
+
 fn build_glob_sets(
     include: &Option<Vec<String>>,
     exclude: &Option<Vec<String>>,
@@ -209,17 +273,16 @@ fn build_glob_sets(
     Ok((include_set, exclude_set))
 }
 
-fn process_text(original: String, operations: &[Operation], pipeline: &Pipeline) -> FileResult {
+fn process_text(original: String, plan: &Plan, pipeline: &Pipeline) -> FileResult {
     // For stdin text, we use a dummy path or "<stdin>"
     let path_buf = PathBuf::from("<stdin>");
 
-    match process_content_inner(original.clone(), operations, pipeline, None) {
-        Ok((modified, replacements, diff, new_content)) => {
+    match process_content_inner(original.as_bytes(), plan, pipeline, None, "<stdin>") {
+        Ok(outcome) => {
             let generated_content = if !pipeline.dry_run {
-                if modified {
-                    Some(new_content)
-                } else {
-                    Some(original)
+                match outcome.content {
+                    Some(content) => Some(String::from_utf8_lossy(&content).into_owned()),
+                    None => Some(original),
                 }
             } else {
                 None
@@ -227,15 +290,21 @@ fn process_text(original: String, operations: &[Operation], pipeline: &Pipeline)
 
             FileResult {
                 path: path_buf,
-                modified,
-                replacements,
+                modified: outcome.modified,
+                replacements: outcome.replacements,
                 error: None,
                 error_code: None,
                 skipped: None,
-                diff,
+                diff: outcome.diff,
                 diff_is_binary: false, // Text input is always treated as text
                 generated_content,
                 is_virtual: true,
+                already_present: outcome.already_present,
+                map_replacements: outcome.map_replacements,
+                expected_matches: outcome.expected_matches,
+                operations: outcome.operations,
+                warnings: outcome.warnings,
+                ..Default::default()
             }
         }
         Err(e) => FileResult {
@@ -249,135 +318,229 @@ fn process_text(original: String, operations: &[Operation], pipeline: &Pipeline)
             diff_is_binary: false,
             generated_content: None,
             is_virtual: true,
+            ..Default::default()
         },
     }
 }
 
-/// Process a single file.
-fn process_file(
-    path: &str,
-    operations: &[Operation],
-    pipeline: &Pipeline,
-    matches: Option<&[ReplacementRange]>,
-    enforcer: &PolicyEnforcer,
-) -> (FileResult, Option<StagedEntry>) {
-    let path_buf = PathBuf::from(path);
-
+/// Apply the symlink policy. Returns the result to report if the path must not be processed.
+fn check_symlink(path_buf: &Path, pipeline: &Pipeline) -> Option<FileResult> {
     // Check for symlinks
-    if let Ok(metadata) = fs::symlink_metadata(path) {
+    if let Ok(metadata) = fs::symlink_metadata(path_buf) {
         if metadata.is_symlink() {
             match pipeline.symlinks {
                 Symlinks::Follow => {
                     // Continue to read
                 }
                 Symlinks::Skip => {
-                    return (
-                        FileResult {
-                            path: path_buf,
-                            modified: false,
-                            replacements: 0,
-                            error: None,
-                            error_code: None,
-                            skipped: Some("symlink".into()),
-                            diff: None,
-                            diff_is_binary: false,
-                            generated_content: None,
-                            is_virtual: false,
-                        },
-                        None,
-                    );
+                    return Some(FileResult {
+                        path: path_buf.to_path_buf(),
+                        modified: false,
+                        replacements: 0,
+                        error: None,
+                        error_code: None,
+                        skipped: Some("symlink".into()),
+                        diff: None,
+                        diff_is_binary: false,
+                        generated_content: None,
+                        is_virtual: false,
+                        ..Default::default()
+                    });
                 }
                 Symlinks::Error => {
-                    return (
-                        FileResult {
-                            path: path_buf,
-                            modified: false,
-                            replacements: 0,
-                            error: Some("Encountered symlink with --symlinks error".into()),
-                            error_code: Some("E_SYMLINK".into()),
-                            skipped: None,
-                            diff: None,
-                            diff_is_binary: false,
-                            generated_content: None,
-                            is_virtual: false,
-                        },
-                        None,
-                    );
+                    return Some(FileResult {
+                        path: path_buf.to_path_buf(),
+                        modified: false,
+                        replacements: 0,
+                        error: Some("Encountered symlink with --symlinks error".into()),
+                        error_code: Some("E_SYMLINK".into()),
+                        skipped: None,
+                        diff: None,
+                        diff_is_binary: false,
+                        generated_content: None,
+                        is_virtual: false,
+                        ..Default::default()
+                    });
                 }
             }
         }
     }
 
-    // Read file content
-    let content_bytes = match fs::read(path) {
+    None
+}
+
+/// The result reported for a binary file, according to the binary policy.
+fn binary_result(path_buf: PathBuf, mode: &BinaryFileMode) -> FileResult {
+    match mode {
+        BinaryFileMode::Skip => FileResult {
+            path: path_buf,
+            modified: false,
+            replacements: 0,
+            error: None,
+            error_code: None,
+            skipped: Some("binary file".into()),
+            diff: None,
+            diff_is_binary: true,
+            generated_content: None,
+            is_virtual: false,
+            ..Default::default()
+        },
+        BinaryFileMode::Error => FileResult {
+            path: path_buf,
+            modified: false,
+            replacements: 0,
+            error: Some("Binary file detected".into()),
+            error_code: Some("E_BINARY".into()),
+            skipped: None,
+            diff: None,
+            diff_is_binary: true,
+            generated_content: None,
+            is_virtual: false,
+            ..Default::default()
+        },
+    }
+}
+
+/// Open a file for processing, honouring the symlink policy.
+/// Returns the result to report instead if the file cannot be opened.
+fn open_input(
+    path_buf: &Path,
+    pipeline: &Pipeline,
+) -> std::result::Result<Source, Box<FileResult>> {
+    if let Some(result) = check_symlink(path_buf, pipeline) {
+        return Err(Box::new(result));
+    }
+
+    Source::open(path_buf).map_err(|e| {
+        Box::new(FileResult {
+            path: path_buf.to_path_buf(),
+            modified: false,
+            replacements: 0,
+            error: Some(e.to_string()),
+            error_code: Some(Error::Io(e).code().into()),
+            skipped: None,
+            diff: None,
+            diff_is_binary: false,
+            generated_content: None,
+            is_virtual: false,
+            ..Default::default()
+        })
+    })
+}
+
+/// Decode raw file content to UTF-8, honouring the binary policy.
+/// UTF-8 content is borrowed; the returned encoding converts it back for writing.
+fn decode_input<'a>(
+    path_buf: &Path,
+    bytes: &'a [u8],
+    pipeline: &Pipeline,
+) -> std::result::Result<(Cow<'a, [u8]>, TextEncoding), Box<FileResult>> {
+    let encoding = TextEncoding::detect(bytes, pipeline.encoding);
+    let content_bytes = match encoding.decode(bytes) {
         Ok(b) => b,
+        Err(e) => {
+            return Err(Box::new(FileResult {
+                path: path_buf.to_path_buf(),
+                error: Some(e.to_string()),
+                error_code: Some(e.code().into()),
+                ..Default::default()
+            }));
+        }
+    };
+
+    // Check for binary content (after decoding, so UTF-16 text is not mistaken for binary)
+    if content_bytes.contains(&0) {
+        return Err(Box::new(binary_result(
+            path_buf.to_path_buf(),
+            &pipeline.binary,
+        )));
+    }
+
+    Ok((content_bytes, encoding))
+}
+
+/// Read and decode a whole file, for callers that need an owned copy.
+fn read_input(
+    path: &str,
+    pipeline: &Pipeline,
+) -> std::result::Result<(Vec<u8>, TextEncoding), Box<FileResult>> {
+    let path_buf = PathBuf::from(path);
+    let source = open_input(&path_buf, pipeline)?;
+    let decoded = source.guard(|| {
+        decode_input(&path_buf, &source, pipeline)
+            .map(|(content, encoding)| (content.into_owned(), encoding))
+    });
+    match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => Err(Box::new(FileResult {
+            path: path_buf,
+            error: Some(e.to_string()),
+            error_code: Some(e.code().into()),
+            ..Default::default()
+        })),
+    }
+}
+
+/// Process a single file.
+fn process_file(
+    path: &str,
+    plan: &Plan,
+    pipeline: &Pipeline,
+    matches: Option<&[ReplacementRange]>,
+    enforcer: &PolicyEnforcer,
+) -> (FileResult, Option<StagedEntry>) {
+    let path_buf = PathBuf::from(path);
+
+    let source = match open_input(&path_buf, pipeline) {
+        Ok(source) => source,
+        Err(result) => return (*result, None),
+    };
+
+    // Everything that reads the source runs inside the guard; the outcome owns its content
+    let guarded = source.guard(|| {
+        let (original, encoding) = decode_input(&path_buf, &source, pipeline)?;
+        Ok::<_, Box<FileResult>>((
+            process_content_inner(&original, plan, pipeline, matches, path),
+            encoding,
+        ))
+    });
+    let (processed, encoding) = match guarded {
+        Ok(Ok(processed)) => processed,
+        Ok(Err(result)) => return (*result, None),
         Err(e) => {
             return (
                 FileResult {
                     path: path_buf,
-                    modified: false,
-                    replacements: 0,
                     error: Some(e.to_string()),
-                    error_code: Some(match e.kind() {
-                        std::io::ErrorKind::NotFound => "E_NOT_FOUND".into(),
-                        std::io::ErrorKind::PermissionDenied => "E_ACCES".into(),
-                        _ => "E_IO".into(),
-                    }),
-                    skipped: None,
-                    diff: None,
-                    diff_is_binary: false,
-                    generated_content: None,
-                    is_virtual: false,
+                    error_code: Some(e.code().into()),
+                    ..Default::default()
                 },
                 None,
             );
         }
     };
 
-    // Check for binary content
-    if content_bytes.contains(&0) {
-        match pipeline.binary {
-            BinaryFileMode::Skip => {
-                return (
-                    FileResult {
-                        path: path_buf,
-                        modified: false,
-                        replacements: 0,
-                        error: None,
-                        error_code: None,
-                        skipped: Some("binary file".into()),
-                        diff: None,
-                        diff_is_binary: true,
-                        generated_content: None,
-                        is_virtual: false,
-                    },
-                    None,
-                );
-            }
-            BinaryFileMode::Error => {
-                return (
-                    FileResult {
-                        path: path_buf,
-                        modified: false,
-                        replacements: 0,
-                        error: Some("Binary file detected".into()),
-                        error_code: Some("E_BINARY".into()),
-                        skipped: None,
-                        diff: None,
-                        diff_is_binary: true,
-                        generated_content: None,
-                        is_virtual: false,
-                    },
-                    None,
-                );
-            }
+    let processed = processed.and_then(|mut outcome| {
+        // Encode before the write policy check so unrepresentable text fails dry runs too
+        if let Some(content) = outcome.content.take() {
+            outcome.content = Some(encoding.encode(content)?);
         }
-    }
-
-    let original = String::from_utf8_lossy(&content_bytes).to_string();
-
-    match process_content_inner(original, operations, pipeline, matches) {
-        Ok((modified, replacements, diff, new_content)) => {
+        Ok(outcome)
+    });
+
+    match processed {
+        Ok(ContentOutcome {
+            modified,
+            replacements,
+            already_present,
+            map_replacements,
+            expected_matches,
+            operations,
+            diff,
+            content: new_content,
+            warnings,
+        }) => {
+            let new_content = new_content.unwrap_or_default();
             // Write changes if policy allows
             if enforcer.can_write(modified) {
                 let options = WriteOptions {
@@ -387,7 +550,7 @@ fn process_file(
 
                 if enforcer.should_stage() {
                     // Stage
-                    match stage_file(&path_buf, new_content.as_bytes(), &options) {
+                    match stage_file(&path_buf, &new_content, &options) {
                         Ok(staged) => (
                             FileResult {
                                 path: path_buf,
@@ -400,6 +563,14 @@ fn process_file(
                                 diff_is_binary: false,
                                 generated_content: None,
                                 is_virtual: false,
+                                already_present,
+                                map_replacements,
+                                expected_matches,
+                                operations,
+                                encoding: Some(encoding.encoding),
+                                bom: encoding.bom,
+                                warnings,
+                                ..Default::default()
                             },
                             Some(staged),
                         ),
@@ -415,13 +586,14 @@ fn process_file(
                                 diff_is_binary: false,
                                 generated_content: None,
                                 is_virtual: false,
+                                ..Default::default()
                             },
                             None,
                         ),
                     }
                 } else {
                     // Write immediately
-                    if let Err(e) = write_file(&path_buf, new_content.as_bytes(), &options) {
+                    if let Err(e) = write_file(&path_buf, &new_content, &options) {
                         return (
                             FileResult {
                                 path: path_buf,
@@ -434,6 +606,7 @@ fn process_file(
                                 diff_is_binary: false,
                                 generated_content: None,
                                 is_virtual: false,
+                                ..Default::default()
                             },
                             None,
                         );
@@ -451,6 +624,14 @@ fn process_file(
                             diff_is_binary: false,
                             generated_content: None,
                             is_virtual: false,
+                            already_present,
+                            map_replacements,
+                            expected_matches,
+                            operations,
+                            encoding: Some(encoding.encoding),
+                            bom: encoding.bom,
+                            warnings,
+                            ..Default::default()
                         },
                         None,
                     )
@@ -468,6 +649,14 @@ fn process_file(
                         diff_is_binary: false,
                         generated_content: None,
                         is_virtual: false,
+                        already_present,
+                        map_replacements,
+                        expected_matches,
+                        operations,
+                        encoding: Some(encoding.encoding),
+                        bom: encoding.bom,
+                        warnings,
+                        ..Default::default()
                     },
                     None,
                 )
@@ -485,127 +674,632 @@ fn process_file(
                 diff_is_binary: false,
                 generated_content: None,
                 is_virtual: false,
+                ..Default::default()
             },
             None,
         ),
     }
 }
 
-/// Inner processing logic shared between file and text input
+/// Process a single file line by line, writing straight into the staged temp file.
+/// Used with `Pipeline.stream`, so memory use does not grow with the file size.
+fn process_file_streaming(
+    path_buf: &Path,
+    plan: &Plan,
+    pipeline: &Pipeline,
+    enforcer: &PolicyEnforcer,
+) -> (FileResult, Option<StagedEntry>) {
+    let error_result = |e: Error| FileResult {
+        path: path_buf.to_path_buf(),
+        error: Some(e.to_string()),
+        error_code: Some(e.code().into()),
+        ..Default::default()
+    };
+
+    if let Some(result) = check_symlink(path_buf, pipeline) {
+        return (result, None);
+    }
+
+    let file = match fs::File::open(path_buf) {
+        Ok(f) => f,
+        Err(e) => return (error_result(Error::Io(e)), None),
+    };
+    let mut reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, file);
+
+    let encoding = match reader.fill_buf() {
+        Ok(head) => TextEncoding::detect(head, pipeline.encoding),
+        Err(e) => return (error_result(Error::Io(e)), None),
+    };
+    if encoding.encoding != Encoding::Utf8 {
+        let e = Error::Encoding(format!(
+            "--stream only supports UTF-8 input, not {}",
+            encoding.encoding.name()
+        ));
+        return (error_result(e), None);
+    }
+    if encoding.bom {
+        reader.consume(3);
+    }
+
+    // Without writes the output is discarded; with writes it goes straight to the temp file
+    let file_name = path_buf.to_string_lossy();
+    let streamed = if enforcer.can_write(true) {
+        let options = WriteOptions {
+            no_follow_symlinks: pipeline.symlinks != Symlinks::Follow,
+            permissions: pipeline.permissions.clone(),
+        };
+        let mut outcome = None;
+        stage_file_with(path_buf, &options, |temp| {
+            let mut writer = BufWriter::with_capacity(STREAM_BUFFER_SIZE, temp);
+            writer.write_all(&encoding.encode(Vec::new())?)?;
+            outcome = Some(stream_lines(
+                &mut reader,
+                &mut writer,
+                plan,
+                &file_name,
+                false,
+            )?);
+            writer.flush()?;
+            Ok(())
+        })
+        .map(|staged| (outcome.unwrap_or_default(), Some(staged)))
+    } else {
+        stream_lines(
+            &mut reader,
+            &mut io::sink(),
+            plan,
+            &file_name,
+            pipeline.dry_run,
+        )
+        .map(|outcome| (outcome, None))
+    };
+
+    let (outcome, staged) = match streamed {
+        Ok(streamed) => streamed,
+        Err(e) => return (error_result(e), None),
+    };
+    if outcome.binary {
+        return (
+            binary_result(path_buf.to_path_buf(), &pipeline.binary),
+            None,
+        );
+    }
+
+    // Unmodified output is dropped, which removes its temp file
+    let staged = staged.filter(|_| outcome.modified);
+    let staged = match staged.map(|entry| settle(entry, enforcer)).transpose() {
+        Ok(staged) => staged.flatten(),
+        Err(e) => return (error_result(e), None),
+    };
+
+    (
+        FileResult {
+            path: path_buf.to_path_buf(),
+            modified: outcome.modified,
+            replacements: outcome.replacements,
+            map_replacements: outcome.map_replacements,
+            operations: outcome.operations,
+            diff: outcome.diff,
+            encoding: Some(encoding.encoding),
+            bom: encoding.bom,
+            warnings: outcome.warnings,
+            ..Default::default()
+        },
+        staged,
+    )
+}
+
+/// Reject inputs that a file operation creates or renames a file onto. File operations
+/// run after all content edits, which would otherwise edit whatever was there before.
+fn check_file_operation_targets(
+    file_operations: &[FileOperation],
+    inputs: &[InputItem],
+    cwd: &Path,
+) -> Result<()> {
+    let targets: HashSet<PathBuf> = file_operations
+        .iter()
+        .filter_map(|op| match op {
+            FileOperation::Create { path, .. } => Some(path),
+            FileOperation::Rename { to, .. } => Some(to),
+            FileOperation::Delete { .. } => None,
+        })
+        .map(|path| normalize_path(Path::new(path), cwd))
+        .collect();
+    for input in inputs {
+        let path = match input {
+            InputItem::Path(p) => p.as_path(),
+            InputItem::RipgrepMatch { path, .. } => path.as_path(),
+            InputItem::Patch { patch, .. } => patch.target(),
+            InputItem::StdinText(_) => continue,
+        };
+        if targets.contains(&normalize_path(path, cwd)) {
+            return Err(Error::Validation(format!(
+                "{} is the target of a file operation, which runs after the content edits",
+                path.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Process a single file-level operation.
+///
+/// `planned` records which paths exist once the earlier file operations are applied,
+/// so that operations can build on each other before anything is committed. It is keyed
+/// by normalized path, so `./a` and `a` are the same file.
+fn process_file_operation(
+    op: &FileOperation,
+    pipeline: &Pipeline,
+    enforcer: &PolicyEnforcer,
+    cwd: &Path,
+    planned: &mut HashMap<PathBuf, bool>,
+) -> (FileResult, Option<StagedEntry>) {
+    let exists = |planned: &HashMap<PathBuf, bool>, path: &Path| {
+        planned
+            .get(&normalize_path(path, cwd))
+            .copied()
+            .unwrap_or_else(|| fs::symlink_metadata(path).is_ok())
+    };
+    let io_error = |kind: std::io::ErrorKind, path: &Path, what: &str| {
+        Error::Io(std::io::Error::new(
+            kind,
+            format!("{} {}", path.display(), what),
+        ))
+    };
+    let failed = |path: PathBuf, e: Error| {
+        (
+            FileResult {
+                path,
+                error: Some(e.to_string()),
+                error_code: Some(e.code().into()),
+                ..Default::default()
+            },
+            None,
+        )
+    };
+    let can_write = enforcer.can_write(true);
+
+    let (path, change, diff, staged) = match op {
+        FileOperation::Create {
+            path,
+            content,
+            overwrite,
+        } => {
+            let path = PathBuf::from(path);
+            if !overwrite && exists(planned, &path) {
+                let e = io_error(std::io::ErrorKind::AlreadyExists, &path, "already exists");
+                return failed(path, e);
+            }
+            let diff = if pipeline.dry_run {
+                generate_diff(b"", content.as_bytes())
+            } else {
+                None
+            };
+            let staged = if can_write {
+                let options = WriteOptions {
+                    no_follow_symlinks: pipeline.symlinks != Symlinks::Follow,
+                    permissions: pipeline.permissions.clone(),
+                };
+                match stage_new_file(&path, content.as_bytes(), &options) {
+                    Ok(staged) => Some(staged),
+                    Err(e) => return failed(path, e),
+                }
+            } else {
+                None
+            };
+            planned.insert(normalize_path(&path, cwd), true);
+            (path, FileChange::Created, diff, staged)
+        }
+        FileOperation::Delete { path } => {
+            let path = PathBuf::from(path);
+            if !exists(planned, &path) {
+                let e = io_error(std::io::ErrorKind::NotFound, &path, "does not exist");
+                return failed(path, e);
+            }
+            // Only files are removed; a directory would otherwise fail halfway through the commit
+            let planned_file = planned.contains_key(&normalize_path(&path, cwd));
+            if !planned_file && fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir()) {
+                let e = io_error(std::io::ErrorKind::IsADirectory, &path, "is a directory");
+                return failed(path, e);
+            }
+            let staged = can_write.then(|| StagedEntry::Remove {
+                target: path.clone(),
+            });
+            planned.insert(normalize_path(&path, cwd), false);
+            (path, FileChange::Deleted, None, staged)
+        }
+        FileOperation::Rename {
+            from,
+            to,
+            overwrite,
+        } => {
+            let (from, to) = (PathBuf::from(from), PathBuf::from(to));
+            if !exists(planned, &from) {
+                let e = io_error(std::io::ErrorKind::NotFound, &from, "does not exist");
+                return failed(from, e);
+            }
+            if !overwrite && exists(planned, &to) {
+                let e = io_error(std::io::ErrorKind::AlreadyExists, &to, "already exists");
+                return failed(to, e);
+            }
+            let staged = can_write.then(|| StagedEntry::Rename {
+                from: from.clone(),
+                to: to.clone(),
+                overwrite: *overwrite,
+            });
+            planned.insert(normalize_path(&from, cwd), false);
+            planned.insert(normalize_path(&to, cwd), true);
+            (to, FileChange::Renamed { from }, None, staged)
+        }
+    };
+
+    let staged = match staged.map(|entry| settle(entry, enforcer)).transpose() {
+        Ok(staged) => staged.flatten(),
+        Err(e) => return failed(path, e),
+    };
+
+    (
+        FileResult {
+            path,
+            modified: true,
+            diff,
+            change: Some(change),
+            ..Default::default()
+        },
+        staged,
+    )
+}
+
+/// Commit `entry` right away unless it belongs to a transaction.
+fn settle(entry: StagedEntry, enforcer: &PolicyEnforcer) -> Result<Option<StagedEntry>> {
+    if enforcer.should_stage() {
+        Ok(Some(entry))
+    } else {
+        entry.commit()?;
+        Ok(None)
+    }
+}
+
+/// Apply the hunks of one file from a unified diff.
+///
+/// A file is only written if every hunk applies; each rejected hunk is reported separately.
+/// A renamed file is read from its old path and written to its new one.
+fn process_patch(
+    patch: &FilePatch,
+    fuzz: usize,
+    pipeline: &Pipeline,
+    enforcer: &PolicyEnforcer,
+) -> Vec<(FileResult, Option<StagedEntry>)> {
+    let path = patch.target().to_path_buf();
+    let source = patch.source().to_path_buf();
+    let failed = |e: Error| {
+        vec![(
+            FileResult {
+                path: path.clone(),
+                error: Some(e.to_string()),
+                error_code: Some(e.code().into()),
+                ..Default::default()
+            },
+            None,
+        )]
+    };
+
+    if (patch.is_creation() || patch.is_rename()) && fs::symlink_metadata(&path).is_ok() {
+        return failed(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        )));
+    }
+    let options = WriteOptions {
+        no_follow_symlinks: pipeline.symlinks != Symlinks::Follow,
+        permissions: pipeline.permissions.clone(),
+    };
+
+    // A pure rename moves the file as it is, whatever its content
+    if patch.is_rename() && patch.hunks.is_empty() {
+        if fs::symlink_metadata(&source).is_err() {
+            return failed(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} does not exist", source.display()),
+            )));
+        }
+        let entry = StagedEntry::Rename {
+            from: source.clone(),
+            to: path.clone(),
+            overwrite: false,
+        };
+        let staged = if enforcer.can_write(true) {
+            match settle(entry, enforcer) {
+                Ok(staged) => staged,
+                Err(e) => return failed(e),
+            }
+        } else {
+            None
+        };
+        return vec![(
+            FileResult {
+                path,
+                modified: true,
+                change: Some(FileChange::Renamed { from: source }),
+                ..Default::default()
+            },
+            staged,
+        )];
+    }
+
+    let (original, encoding) = if patch.is_creation() {
+        let encoding = TextEncoding {
+            encoding: pipeline.encoding.unwrap_or_default(),
+            bom: false,
+        };
+        (Vec::new(), encoding)
+    } else {
+        match read_input(&source.to_string_lossy(), pipeline) {
+            Ok(input) => input,
+            Err(result) => return vec![(*result, None)],
+        }
+    };
+
+    let outcome = patch.apply(&original, fuzz);
+    if !outcome.rejected.is_empty() {
+        return outcome
+            .rejected
+            .iter()
+            .flat_map(|r| {
+                failed(Error::HunkRejected(format!(
+                    "hunk #{} (line {}) does not match {}",
+                    r.hunk,
+                    r.line,
+                    source.display()
+                )))
+            })
+            .collect();
+    }
+    if patch.is_deletion() && !outcome.content.is_empty() {
+        return failed(Error::HunkRejected(format!(
+            "{} is not empty after removing the patched lines",
+            path.display()
+        )));
+    }
+
+    let change = if patch.is_creation() {
+        Some(FileChange::Created)
+    } else if patch.is_deletion() {
+        Some(FileChange::Deleted)
+    } else if patch.is_rename() {
+        Some(FileChange::Renamed {
+            from: source.clone(),
+        })
+    } else {
+        None
+    };
+    let modified = change.is_some() || outcome.content != original;
+    let diff = if pipeline.dry_run {
+        generate_diff(&original, &outcome.content)
+    } else {
+        None
+    };
+
+    let content = if modified && !patch.is_deletion() {
+        match encoding.encode(outcome.content) {
+            Ok(content) => content,
+            Err(e) => return failed(e),
+        }
+    } else {
+        outcome.content
+    };
+
+    let staged = if enforcer.can_write(modified) {
+        let entry = if patch.is_deletion() {
+            Ok(StagedEntry::Remove {
+                target: path.clone(),
+            })
+        } else if patch.is_creation() {
+            stage_new_file(&path, &content, &options)
+        } else if patch.is_rename() {
+            stage_move(&source, &path, &content, &options)
+        } else {
+            stage_file(&path, &content, &options)
+        };
+        match entry.and_then(|entry| settle(entry, enforcer)) {
+            Ok(staged) => staged,
+            Err(e) => return failed(e),
+        }
+    } else {
+        None
+    };
+
+    vec![(
+        FileResult {
+            path,
+            modified,
+            replacements: outcome.applied,
+            diff,
+            change,
+            encoding: Some(encoding.encoding),
+            bom: encoding.bom,
+            ..Default::default()
+        },
+        staged,
+    )]
+}
+
+/// Outcome of applying all operations to one input.
+struct ContentOutcome {
+    modified: bool,
+    replacements: usize,
+    /// Number of ensure operations whose content was already present.
+    already_present: usize,
+    /// Replacements per `replace_map` key.
+    map_replacements: BTreeMap<String, usize>,
+    /// Match locations of the operations with an `expect` / `expect_per_file`, by index.
+    expected_matches: BTreeMap<usize, Vec<(usize, usize)>>,
+    /// What each operation did, in pipeline order.
+    operations: Vec<OperationStats>,
+    diff: Option<String>,
+    /// The new content, or `None` if the input is unchanged.
+    content: Option<Vec<u8>>,
+    /// Non-fatal problems found in the result, such as mixed line endings.
+    warnings: Vec<String>,
+}
+
+/// Inner processing logic shared between file and text input.
+/// The input is only copied once an operation changes it.
 fn process_content_inner(
-    original: String,
-    operations: &[Operation],
+    original: &[u8],
+    plan: &Plan,
     pipeline: &Pipeline,
     matches: Option<&[ReplacementRange]>,
-) -> Result<(bool, usize, Option<String>, String)> {
+    file: &str,
+) -> Result<ContentOutcome> {
     // Apply each operation sequentially
-    let mut current = original.clone();
+    let mut current = Cow::Borrowed(original);
     let mut total_replacements = 0;
-
-    for op in operations {
-        match op {
-            Operation::Replace {
-                find,
-                with: replacement,
-                literal,
-                ignore_case,
-                smart_case,
-                word,
-                multiline,
-                dot_matches_newline,
-                no_unicode,
-                limit,
-                range,
-                expand,
-                validation_mode,
+    let mut already_present = 0;
+    let mut map_replacements = BTreeMap::new();
+    let mut expected_matches = BTreeMap::new();
+    let mut operations = Vec::with_capacity(plan.steps.len());
+    let mut run_counts = Vec::new();
+
+    for (index, step) in plan.steps.iter().enumerate() {
+        // Inserted text follows the file's current line ending convention
+        let line_ending = EolStats::count(&current).dominant();
+
+        // The step's count toward the replacements, and its output if it made one
+        let (count, edited) = match step {
+            Step::Replace {
+                replacer,
+                name,
+                locate,
             } => {
-                // Build replacer
-                let replacer = Replacer::new(
-                    find,
-                    replacement,
-                    *literal,
-                    *ignore_case,
-                    *smart_case,
-                    *word,
-                    *multiline,
-                    false, // single_line (not yet supported)
-                    *dot_matches_newline,
-                    *no_unicode,
-                    *limit,
-                    range.clone(),
-                    matches.map(|m| m.to_vec()),
-                    *expand,
-                    *validation_mode,
+                let replacer = replacer.scoped(line_ending, matches);
+                if *locate {
+                    expected_matches.insert(index, replacer.locate_matches(&current)?);
+                }
+                let vars = TemplateVars {
+                    file,
+                    global_offset: plan.run_count(index),
+                    ..Default::default()
+                };
+                let (bytes, replacements) = replacer.replace_with_count(&current, &vars)?;
+                run_counts.push((index, replacements));
+                (
+                    replacements,
+                    owned(bytes).map(|b| check_utf8(&current, b, name)),
                 )
-                .map_err(|e| Error::Validation(e.to_string()))?;
-
-                // Apply replacement to current string (as bytes) and count replacements
-                let (bytes, replacements) = replacer.replace_with_count(current.as_bytes());
-                let new_string = String::from_utf8(bytes.to_vec()).map_err(|e| {
-                    Error::Validation(format!("Invalid UTF-8 after replacement: {}", e))
-                })?;
-
-                current = new_string;
-                total_replacements += replacements;
             }
-            Operation::Delete {
-                find,
-                literal,
-                ignore_case,
-                smart_case,
-                word,
-                multiline,
-                dot_matches_newline,
-                no_unicode,
-                limit,
-                range,
-            } => {
-                // Build replacer with empty replacement
-                let replacer = Replacer::new(
-                    find,
-                    "", // empty replacement
-                    *literal,
-                    *ignore_case,
-                    *smart_case,
-                    *word,
-                    *multiline,
-                    false, // single_line
-                    *dot_matches_newline,
-                    *no_unicode,
-                    *limit,
-                    range.clone(),
-                    matches.map(|m| m.to_vec()),
-                    false, // expand (no need for empty string)
-                    crate::model::ValidationMode::default(),
+            Step::Lines { replacer, action } => {
+                let replacer = replacer.scoped(line_ending, matches);
+                let (bytes, lines) = replacer.apply_lines(&current, *action);
+                (
+                    lines,
+                    owned(bytes).map(|b| check_utf8(&current, b, "line edit")),
+                )
+            }
+            Step::Block(replacer) => {
+                let (bytes, blocks) = replacer.get(line_ending).replace_with_count(&current)?;
+                (
+                    blocks,
+                    owned(bytes).map(|b| check_utf8(&current, b, "replace_block")),
+                )
+            }
+            Step::Map { replacer, name } => {
+                let replacer = replacer.scoped(line_ending, matches);
+                let (bytes, counts) = replacer.replace_with_counts(&current);
+                let replacements = replacer.tally(counts, &mut map_replacements);
+                (
+                    replacements,
+                    owned(bytes).map(|b| check_utf8(&current, b, name)),
                 )
-                .map_err(|e| Error::Validation(e.to_string()))?;
-
-                let (bytes, replacements) = replacer.replace_with_count(current.as_bytes());
-                let new_string = String::from_utf8(bytes.to_vec())
-                    .map_err(|e| Error::Validation(format!("Invalid UTF-8 after delete: {}", e)))?;
-
-                current = new_string;
-                total_replacements += replacements;
             }
+            Step::Ensure(ensurer) => match ensurer.get(line_ending).apply(&current)? {
+                Some(bytes) => (1, Some(check_utf8(&current, bytes, "ensure"))),
+                None => {
+                    already_present += 1;
+                    (0, None)
+                }
+            },
+            Step::NormalizeEol(target) => {
+                let (bytes, lines) = eol::normalize(&current, *target);
+                (lines, owned(bytes).map(Ok))
+            }
+        };
+
+        total_replacements += count;
+        let mut changed = false;
+        if let Some(bytes) = edited.transpose()? {
+            changed = bytes != *current;
+            current = Cow::Owned(bytes);
         }
+        operations.push(step.stats(index, count, changed));
     }
 
-    let modified = current != original;
-    let diff = if pipeline.dry_run {
-        generate_diff(&original, &current)
+    // Only a file whose every step succeeded advances `${__global}` for the next one
+    for (index, count) in run_counts {
+        plan.add_run_count(index, count);
+    }
+
+    let warnings = EolStats::count(&current)
+        .mixed_warning()
+        .into_iter()
+        .collect();
+
+    let modified = matches!(current, Cow::Owned(_)) && *current != *original;
+    let diff = if pipeline.dry_run && modified {
+        generate_diff(original, &current)
     } else {
         None
     };
 
-    Ok((modified, total_replacements, diff, current))
+    Ok(ContentOutcome {
+        modified,
+        replacements: total_replacements,
+        already_present,
+        map_replacements,
+        expected_matches,
+        operations,
+        diff,
+        content: modified.then(|| current.into_owned()),
+        warnings,
+    })
+}
+
+/// The new bytes of an edit, or `None` if it left its input untouched.
+fn owned(bytes: Cow<'_, [u8]>) -> Option<Vec<u8>> {
+    match bytes {
+        Cow::Owned(bytes) => Some(bytes),
+        Cow::Borrowed(_) => None,
+    }
+}
+
+/// Reject an edit that adds invalid UTF-8.
+///
+/// Invalid sequences already present in the input are preserved as-is, so only an
+/// increase in their number counts as corruption.
+pub fn check_utf8(before: &[u8], after: Vec<u8>, op: &str) -> Result<Vec<u8>> {
+    let invalid = |bytes: &[u8]| {
+        bytes
+            .utf8_chunks()
+            .filter(|chunk| !chunk.invalid().is_empty())
+            .count()
+    };
+    if std::str::from_utf8(&after).is_err() && invalid(&after) > invalid(before) {
+        return Err(Error::InvalidUtf8(format!(
+            "{} would produce invalid UTF-8",
+            op
+        )));
+    }
+    Ok(after)
 }
 
 /// Generate a unified diff between old and new content.
-fn generate_diff(old: &str, new: &str) -> Option<String> {
+/// Invalid UTF-8 is replaced with U+FFFD for display only.
+fn generate_diff(old: &[u8], new: &[u8]) -> Option<String> {
     if old == new {
         return None;
     }
-    let diff = TextDiff::from_lines(old, new);
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let diff = TextDiff::from_lines(old.as_ref(), new.as_ref());
     let mut output = String::new();
     for change in diff.iter_all_changes() {
         let sign = match change.tag() {
@@ -613,17 +1307,6 @@ fn generate_diff(old: &str, new: &str) -> Option<String> {
             ChangeTag::Insert => "+",
             ChangeTag::Equal => " ",
         };
-        // Sanitize: replace any non-UTF8 or weird control chars if they slipped through?
-        // Actually, inputs are already Strings (valid UTF-8), but might contain control chars.
-        // The requirement is: "force-sanitize invalid UTF-8 in the `diff` string to the replacement character"
-        // Since input is `&str`, it is already valid UTF-8.
-        // However, if we want to be extra safe against JSON serialization issues with control chars (though serde handles most):
-        // But the prompt says "If a file is technically text but contains a rogue null byte or invalid UTF-8 sequence that your diff engine tries to include".
-        // We already converted bytes to String using `String::from_utf8_lossy` earlier in `process_file`, so we shouldn't have invalid UTF-8 here.
-        // `from_utf8_lossy` replaces invalid sequences with .
-        // So `old` and `new` are safe.
-        // But let's just make sure we don't return something that breaks.
-
         output.push_str(&format!("{}{}", sign, change));
         if change.missing_newline() {
             output.push_str("\n\\ No newline at end of file\n");
@@ -673,7 +1356,7 @@ fn normalize_path(path: &Path, cwd: &Path) -> PathBuf {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::model::{Operation, Pipeline};
+    use crate::model::{Operation, Pipeline, RegexEngine, Whitespace};
 
     fn pipeline(dry_run: bool, validate_only: bool) -> Pipeline {
         Pipeline {
@@ -683,6 +1366,10 @@ mod tests {
         }
     }
 
+    fn plan(ops: &[Operation]) -> Plan {
+        Plan::compile(ops).unwrap()
+    }
+
     fn op_replace(find: &str, with: &str) -> Operation {
         Operation::Replace {
             find: find.into(),
@@ -696,8 +1383,17 @@ mod tests {
             no_unicode: false,
             limit: 0,
             range: None,
+            occurrence: None,
+            before: None,
+            after: None,
+            engine: RegexEngine::default(),
+            backtrack_limit: None,
+            whitespace: Whitespace::default(),
+            expect: None,
+            expect_per_file: None,
             expand: false,
             validation_mode: crate::model::ValidationMode::default(),
+            preserve_case: false,
         }
     }
 
@@ -706,14 +1402,13 @@ mod tests {
         let p = pipeline(true, false);
         let ops = vec![op_replace("world", "there")];
 
-        let original = "hello world\n".to_string();
-        let (modified, replacements, diff, new_content) =
-            process_content_inner(original.clone(), &ops, &p, None).unwrap();
+        let original = b"hello world\n".to_vec();
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
 
-        assert!(modified);
-        assert_eq!(replacements, 1);
-        assert_eq!(new_content, "hello there\n");
-        assert!(diff.is_some());
+        assert!(outcome.modified);
+        assert_eq!(outcome.replacements, 1);
+        assert_eq!(outcome.content.unwrap(), b"hello there\n");
+        assert!(outcome.diff.is_some());
     }
 
     #[test]
@@ -721,14 +1416,13 @@ mod tests {
         let p = pipeline(true, false);
         let ops = vec![op_replace("zzz", "yyy")];
 
-        let original = "abc\n".to_string();
-        let (modified, replacements, diff, new_content) =
-            process_content_inner(original.clone(), &ops, &p, None).unwrap();
+        let original = b"abc\n".to_vec();
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
 
-        assert!(!modified);
-        assert_eq!(replacements, 0);
-        assert_eq!(new_content, original);
-        assert!(diff.is_none());
+        assert!(!outcome.modified);
+        assert_eq!(outcome.replacements, 0);
+        assert!(outcome.content.is_none());
+        assert!(outcome.diff.is_none());
     }
 
     #[test]
@@ -736,21 +1430,237 @@ mod tests {
         let p = pipeline(false, false);
         let ops = vec![op_replace("a", "b")];
 
-        let original = "a\n".to_string();
-        let (_modified, _replacements, diff, _new_content) =
-            process_content_inner(original, &ops, &p, None).unwrap();
+        let original = b"a\n".to_vec();
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
 
-        assert!(diff.is_none());
+        assert!(outcome.diff.is_none());
+    }
+
+    #[test]
+    fn process_content_inner_inserts_before_and_after() {
+        let p = pipeline(false, false);
+        let ops = vec![
+            Operation::InsertAfter {
+                find: r"^use .*\n".into(),
+                text: "use c;\n".into(),
+                literal: false,
+                ignore_case: false,
+                smart_case: false,
+                word: false,
+                multiline: true,
+                dot_matches_newline: false,
+                no_unicode: false,
+                limit: 0,
+                range: Some(crate::model::LineRange {
+                    start: 2,
+                    end: Some(2),
+                }),
+            },
+            Operation::InsertBefore {
+                find: "fn main".into(),
+                text: "// entry\n".into(),
+                literal: true,
+                ignore_case: false,
+                smart_case: false,
+                word: false,
+                multiline: false,
+                dot_matches_newline: false,
+                no_unicode: false,
+                limit: 0,
+                range: None,
+            },
+        ];
+
+        let original = b"use a;\nuse b;\n\nfn main() {}\n".to_vec();
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
+
+        assert!(outcome.modified);
+        assert_eq!(outcome.replacements, 2);
+        assert_eq!(
+            outcome.content.unwrap(),
+            b"use a;\nuse b;\nuse c;\n\n// entry\nfn main() {}\n"
+        );
+    }
+
+    fn op_delete_lines(find: &str, range: Option<crate::model::LineRange>) -> Operation {
+        Operation::DeleteLines {
+            find: find.into(),
+            literal: true,
+            ignore_case: false,
+            smart_case: false,
+            word: false,
+            no_unicode: false,
+            limit: 0,
+            range,
+        }
+    }
+
+    #[test]
+    fn process_content_inner_line_operations_keep_line_endings() {
+        let p = pipeline(false, false);
+        let original = b"keep a\r\ndrop b\r\nkeep c\r\ndrop d".to_vec();
+
+        let ops = vec![op_delete_lines("drop", None)];
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.replacements, 2);
+        assert_eq!(outcome.content.unwrap(), b"keep a\r\nkeep c\r\n");
+
+        let ops = vec![Operation::ReplaceLine {
+            find: "^drop".into(),
+            with: "replaced".into(),
+            literal: false,
+            ignore_case: false,
+            smart_case: false,
+            word: false,
+            no_unicode: false,
+            limit: 0,
+            range: None,
+        }];
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.replacements, 2);
+        assert_eq!(
+            outcome.content.unwrap(),
+            b"keep a\r\nreplaced\r\nkeep c\r\nreplaced"
+        );
+
+        let ops = vec![Operation::KeepLines {
+            find: "keep".into(),
+            literal: true,
+            ignore_case: false,
+            smart_case: false,
+            word: false,
+            no_unicode: false,
+            range: Some(crate::model::LineRange {
+                start: 1,
+                end: Some(2),
+            }),
+        }];
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.replacements, 1);
+        assert_eq!(outcome.content.unwrap(), b"keep a\r\nkeep c\r\ndrop d");
+    }
+
+    #[test]
+    fn process_content_inner_delete_lines_respects_range() {
+        let p = pipeline(false, false);
+        let ops = vec![op_delete_lines(
+            "x",
+            Some(crate::model::LineRange {
+                start: 2,
+                end: None,
+            }),
+        )];
+
+        let outcome =
+            process_content_inner(b"x1\nx2\ny\nx3\n", &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.replacements, 2);
+        assert_eq!(outcome.content.unwrap(), b"x1\ny\n");
+    }
+
+    #[test]
+    fn process_content_inner_ensure_line_is_idempotent() {
+        let p = pipeline(false, false);
+        let ops = vec![Operation::EnsureLine {
+            line: "use c;".into(),
+            position: crate::model::EnsurePosition::After,
+            anchor: Some("use b;".into()),
+            literal: true,
+            ignore_case: false,
+        }];
+
+        let first =
+            process_content_inner(b"use a;\nuse b;\n", &plan(&ops), &p, None, "test.txt").unwrap();
+        assert!(first.modified);
+        assert_eq!(first.replacements, 1);
+        assert_eq!(first.already_present, 0);
+        assert_eq!(
+            first.content.as_deref().unwrap(),
+            b"use a;\nuse b;\nuse c;\n"
+        );
+
+        let second =
+            process_content_inner(&first.content.unwrap(), &plan(&ops), &p, None, "test.txt")
+                .unwrap();
+        assert!(!second.modified);
+        assert_eq!(second.replacements, 0);
+        assert_eq!(second.already_present, 1);
+    }
+
+    #[test]
+    fn process_content_inner_preserves_invalid_utf8() {
+        let p = pipeline(true, false);
+        let ops = vec![op_replace("foo", "bar")];
+
+        let outcome =
+            process_content_inner(b"\xff foo\n", &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.content.unwrap(), b"\xff bar\n");
+        assert!(outcome.diff.unwrap().contains("+\u{FFFD} bar"));
+    }
+
+    #[test]
+    fn check_utf8_rejects_new_invalid_sequences() {
+        assert!(check_utf8(b"\xff a", b"\xff b".to_vec(), "replace").is_ok());
+        let err = check_utf8("é".as_bytes(), b"\xc3".to_vec(), "replace").unwrap_err();
+        assert_eq!(err.code(), "E_INVALID_UTF8");
+    }
+
+    #[test]
+    fn process_content_inner_failed_file_does_not_advance_global() {
+        let p = pipeline(false, false);
+        let mut op = op_replace(r"x|(?-u:\xa9)", "${__global}");
+        if let Operation::Replace {
+            literal, expand, ..
+        } = &mut op
+        {
+            (*literal, *expand) = (false, true);
+        }
+        let plan = plan(&[op]);
+
+        let result = process_content_inner("x é".as_bytes(), &plan, &p, None, "a.txt");
+        assert!(matches!(result, Err(Error::InvalidUtf8(_))));
+        let outcome = process_content_inner(b"x", &plan, &p, None, "b.txt").unwrap();
+        assert_eq!(outcome.content.unwrap(), b"1");
+    }
+
+    #[test]
+    fn process_content_inner_matches_line_endings() {
+        let p = pipeline(false, false);
+        let ops = vec![op_replace("b", "b1\nb2")];
+
+        let outcome =
+            process_content_inner(b"a\r\nb\r\n", &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.content.unwrap(), b"a\r\nb1\r\nb2\r\n");
+        assert!(outcome.warnings.is_empty());
+
+        let outcome = process_content_inner(b"a\nb\n", &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.content.unwrap(), b"a\nb1\nb2\n");
+    }
+
+    #[test]
+    fn process_content_inner_normalize_eol() {
+        let p = pipeline(false, false);
+        let original = b"a\r\nb\nc\r\n".to_vec();
+
+        let outcome = process_content_inner(&original, &plan(&[]), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.warnings, vec!["mixed line endings (2 CRLF, 1 LF)"]);
+
+        let ops = vec![Operation::NormalizeEol {
+            eol: crate::model::LineEnding::Lf,
+        }];
+        let outcome = process_content_inner(&original, &plan(&ops), &p, None, "test.txt").unwrap();
+        assert_eq!(outcome.content.unwrap(), b"a\nb\nc\n");
+        assert_eq!(outcome.replacements, 2);
+        assert!(outcome.warnings.is_empty());
     }
 
     #[test]
     fn generate_diff_returns_none_when_equal() {
-        assert_eq!(generate_diff("x\n", "x\n"), None);
+        assert_eq!(generate_diff(b"x\n", b"x\n"), None);
     }
 
     #[test]
     fn generate_diff_shows_insert_and_delete_markers() {
-        let d = generate_diff("a\n", "b\n").unwrap();
+        let d = generate_diff(b"a\n", b"b\n").unwrap();
         assert!(d.contains("-a"));
         assert!(d.contains("+b"));
     }
@@ -785,6 +1695,20 @@ mod tests {
         assert!(err.to_string().contains("No operations specified"));
     }
 
+    #[test]
+    fn execute_fails_on_invalid_operation_before_processing() {
+        let mut p = pipeline(false, false);
+        let mut op = op_replace("(", "x");
+        if let Operation::Replace { literal, .. } = &mut op {
+            *literal = false;
+        }
+        p.operations = vec![op];
+        // The missing file is never read: compilation fails first
+        let inputs = vec![InputItem::Path(PathBuf::from("does-not-exist.txt"))];
+        let err = execute(p, inputs).unwrap_err();
+        assert_eq!(err.code(), "E_VALIDATION");
+    }
+
     #[test]
     fn execute_validate_only_forces_dry_run_and_generates_diff() {
         let mut p = pipeline(false, true);
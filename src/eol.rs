@@ -0,0 +1,94 @@
+use crate::model::LineEnding;
+use std::borrow::Cow;
+
+/// Line ending counts of a text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EolStats {
+    pub crlf: usize,
+    pub lf: usize,
+}
+
+impl EolStats {
+    pub fn count(text: &[u8]) -> Self {
+        let mut stats = Self::default();
+        for i in memchr::memchr_iter(b'\n', text) {
+            if i > 0 && text[i - 1] == b'\r' {
+                stats.crlf += 1;
+            } else {
+                stats.lf += 1;
+            }
+        }
+        stats
+    }
+
+    /// The most common line ending. Ties and texts without newlines count as LF.
+    pub fn dominant(&self) -> LineEnding {
+        if self.crlf > self.lf {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn is_mixed(&self) -> bool {
+        self.crlf > 0 && self.lf > 0
+    }
+
+    /// The warning reported for a result with mixed line endings.
+    pub fn mixed_warning(&self) -> Option<String> {
+        self.is_mixed()
+            .then(|| format!("mixed line endings ({} CRLF, {} LF)", self.crlf, self.lf))
+    }
+}
+
+/// Convert every line ending in `text` to `eol`. Returns the number of lines changed.
+pub fn normalize(text: &[u8], eol: LineEnding) -> (Cow<'_, [u8]>, usize) {
+    let stats = EolStats::count(text);
+    let changed = match eol {
+        LineEnding::Lf => stats.crlf,
+        LineEnding::Crlf => stats.lf,
+    };
+    if changed == 0 {
+        return (Cow::Borrowed(text), 0);
+    }
+
+    let mut out = Vec::with_capacity(text.len() + stats.lf);
+    let mut last = 0;
+    for i in memchr::memchr_iter(b'\n', text) {
+        let line_end = if i > 0 && text[i - 1] == b'\r' {
+            i - 1
+        } else {
+            i
+        };
+        out.extend_from_slice(&text[last..line_end]);
+        out.extend_from_slice(eol.as_bytes());
+        last = i + 1;
+    }
+    out.extend_from_slice(&text[last..]);
+    (Cow::Owned(out), changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_picks_dominant() {
+        let stats = EolStats::count(b"a\r\nb\r\nc\nd");
+        assert_eq!(stats, EolStats { crlf: 2, lf: 1 });
+        assert_eq!(stats.dominant(), LineEnding::Crlf);
+        assert!(stats.is_mixed());
+        assert_eq!(EolStats::count(b"no newline").dominant(), LineEnding::Lf);
+    }
+
+    #[test]
+    fn normalizes_both_ways() {
+        let (out, n) = normalize(b"a\r\nb\nc", LineEnding::Lf);
+        assert_eq!((out.as_ref(), n), (&b"a\nb\nc"[..], 1));
+        let (out, n) = normalize(b"a\r\nb\nc", LineEnding::Crlf);
+        assert_eq!((out.as_ref(), n), (&b"a\r\nb\r\nc"[..], 1));
+        let (out, n) = normalize(b"a\nb\n", LineEnding::Lf);
+        assert!(matches!(out, Cow::Borrowed(_)));
+        assert_eq!(n, 0);
+    }
+}
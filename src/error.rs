@@ -43,6 +43,24 @@ pub enum Error {
 
     #[error("Transaction failed (partial application): {0}")]
     TransactionFailure(String),
+
+    #[error("Unbalanced block markers: {0}")]
+    BlockMarkers(String),
+
+    #[error("Patch hunk rejected: {0}")]
+    HunkRejected(String),
+
+    #[error("Invalid UTF-8: {0}")]
+    InvalidUtf8(String),
+
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+
+    #[error("Input changed while reading: {0}")]
+    Truncated(String),
+
+    #[error("Regex backtrack limit of {0} steps exceeded")]
+    BacktrackLimit(usize),
 }
 
 impl Error {
@@ -66,6 +84,12 @@ impl Error {
             Error::FailedJobs(_) => "E_FAILED_JOBS",
             Error::InvalidPath(_) => "E_INVALID_PATH",
             Error::TransactionFailure(_) => "E_TRANSACTION",
+            Error::BlockMarkers(_) => "E_BLOCK_MARKERS",
+            Error::HunkRejected(_) => "E_HUNK_REJECTED",
+            Error::InvalidUtf8(_) => "E_INVALID_UTF8",
+            Error::Encoding(_) => "E_ENCODING",
+            Error::Truncated(_) => "E_TRUNCATED",
+            Error::BacktrackLimit(_) => "E_BACKTRACK_LIMIT",
         }
     }
 }
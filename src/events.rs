@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,7 +15,7 @@ pub struct RunStart {
     pub schema_version: String,
     pub tool_version: String,
     pub mode: String,             // "cli" or "apply"
-    pub input_mode: String, // "args", "stdin-paths", "stdin-text", "rg-json", "files0", "manifest"
+    pub input_mode: String, // "args", "stdin-paths", "stdin-text", "rg-json", "files0", "patch", "walk"
     pub transaction_mode: String, // "all" or "file"
     pub dry_run: bool,
     pub validate_only: bool,
@@ -44,6 +45,31 @@ pub enum FileEvent {
         diff_is_binary: bool,
         #[serde(default)]
         is_virtual: bool,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        already_present: usize,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        map_replacements: BTreeMap<String, usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        encoding: Option<String>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        bom: bool,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+        /// Per-operation breakdown, in pipeline order.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        operations: Vec<OperationStats>,
+    },
+    Created {
+        path: PathBuf,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        diff: Option<String>,
+    },
+    Deleted {
+        path: PathBuf,
+    },
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
     },
     Skipped {
         path: PathBuf,
@@ -56,6 +82,19 @@ pub enum FileEvent {
     },
 }
 
+/// What one operation of the pipeline did to a file, or to all files in `RunEnd`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct OperationStats {
+    /// Index of the operation in the pipeline.
+    pub index: usize,
+    /// Matches (or lines, blocks, insertions) the operation counted toward `replacements`.
+    pub matches: usize,
+    /// Whether the operation changed the content.
+    pub changed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum SkipReason {
@@ -77,6 +116,13 @@ pub struct RunEnd {
     pub committed: bool,
     pub duration_ms: u64,
     pub exit_code: i32,
+    /// Per-operation breakdown summed over all files; warnings are prefixed with the path.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operations: Vec<OperationStats>,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 #[cfg(test)]
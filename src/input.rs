@@ -1,8 +1,9 @@
 use crate::error::{Error, Result};
 use crate::model::ReplacementRange;
+use crate::patch::{parse_patch, FilePatch};
 use crate::rgjson::{stream_rg_json_ndjson, DeinterleavingSink};
 use std::io::{self, BufRead, BufReader, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum InputMode {
@@ -17,6 +18,22 @@ pub enum InputMode {
     StdinText,
     /// Read ripgrep JSON from stdin.
     RipgrepJson,
+    /// Read a unified diff from a file (or stdin for `-`).
+    Patch(PathBuf),
+    /// Walk a directory tree (requires the `walk` feature).
+    Walk(PathBuf),
+}
+
+/// How `--walk` traverses a directory tree.
+#[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(not(feature = "walk"), allow(dead_code))]
+pub struct WalkOptions {
+    /// Include hidden files and directories.
+    pub hidden: bool,
+    /// Do not honor .gitignore, .ignore and git exclude files.
+    pub no_ignore: bool,
+    /// Maximum depth below the root (the root's direct children are depth 1).
+    pub max_depth: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -27,17 +44,28 @@ pub enum InputItem {
         path: PathBuf,
         matches: Vec<ReplacementRange>,
     },
+    Patch {
+        patch: FilePatch,
+        fuzz: usize,
+    },
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn resolve_input_mode(
     stdin_paths: bool,
     files0: bool,
     stdin_text: bool,
     rg_json: bool,
+    patch: Option<&Path>,
+    walk: Option<&Path>,
     files_arg: bool,
     files: &[PathBuf],
 ) -> InputMode {
-    if stdin_text {
+    if let Some(patch) = patch {
+        InputMode::Patch(patch.to_path_buf())
+    } else if let Some(root) = walk {
+        InputMode::Walk(root.to_path_buf())
+    } else if stdin_text {
         InputMode::StdinText
     } else if rg_json {
         InputMode::RipgrepJson
@@ -145,3 +173,98 @@ pub fn read_rg_json() -> Result<Vec<InputItem>> {
 
     Ok(items)
 }
+
+/// Read a unified diff and split it into one input per target file.
+pub fn read_patch(path: &Path, fuzz: usize) -> Result<Vec<InputItem>> {
+    let text = if path == Path::new("-") {
+        read_stdin_text()?
+    } else {
+        std::fs::read_to_string(path).map_err(Error::Io)?
+    };
+    Ok(parse_patch(&text)?
+        .into_iter()
+        .map(|patch| InputItem::Patch { patch, fuzz })
+        .collect())
+}
+
+/// List the regular files under `root`, sorted by path. Symlinks are not followed.
+#[cfg(feature = "walk")]
+pub fn walk_paths(root: &Path, options: WalkOptions) -> Result<Vec<PathBuf>> {
+    let mut builder = ignore::WalkBuilder::new(root);
+    builder
+        .hidden(!options.hidden)
+        .ignore(!options.no_ignore)
+        .git_ignore(!options.no_ignore)
+        .git_global(!options.no_ignore)
+        .git_exclude(!options.no_ignore)
+        .parents(!options.no_ignore)
+        // Honor .gitignore even outside a git checkout
+        .require_git(false)
+        .max_depth(options.max_depth)
+        .sort_by_file_path(|a, b| a.cmp(b));
+
+    let mut paths = Vec::new();
+    for entry in builder.build() {
+        let entry = entry
+            .map_err(|e| Error::Validation(format!("Failed to walk {}: {}", root.display(), e)))?;
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            paths.push(entry.into_path());
+        }
+    }
+    Ok(paths)
+}
+
+/// Without the `walk` feature, `--walk` is rejected.
+#[cfg(not(feature = "walk"))]
+pub fn walk_paths(_root: &Path, _options: WalkOptions) -> Result<Vec<PathBuf>> {
+    Err(Error::Validation(
+        "--walk requires txed to be built with the `walk` feature".into(),
+    ))
+}
+
+#[cfg(all(test, feature = "walk"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn walk_honors_ignore_files_hidden_and_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::write(root.join(".gitignore"), "ignored.txt\n").unwrap();
+        fs::write(root.join("top.txt"), "x").unwrap();
+        fs::write(root.join("ignored.txt"), "x").unwrap();
+        fs::write(root.join(".hidden"), "x").unwrap();
+        fs::write(root.join("sub/mid.txt"), "x").unwrap();
+        fs::write(root.join("sub/deeper/low.txt"), "x").unwrap();
+
+        let names = |options| {
+            walk_paths(root, options)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(WalkOptions::default()),
+            ["sub/deeper/low.txt", "sub/mid.txt", "top.txt"]
+        );
+        assert_eq!(
+            names(WalkOptions {
+                max_depth: Some(1),
+                ..Default::default()
+            }),
+            ["top.txt"]
+        );
+        assert_eq!(
+            names(WalkOptions {
+                hidden: true,
+                no_ignore: true,
+                max_depth: Some(1),
+            }),
+            [".gitignore", ".hidden", "ignored.txt", "top.txt"]
+        );
+    }
+}
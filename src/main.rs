@@ -1,23 +1,30 @@
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
 use std::fs;
 use std::io::IsTerminal;
 
 use crate::cli::{Cli, Commands, DefaultArgs, OutputFormat, PermissionsMode as CliPermissionsMode};
 use crate::input::{InputItem, InputMode};
-use crate::model::{LineRange, Operation, PermissionsMode, Pipeline};
+use crate::model::{LineRange, Occurrence, Operation, PermissionsMode, Pipeline};
 
 mod cli;
+mod encoding;
 mod engine;
+mod eol;
 mod error;
 mod events;
 mod exit_codes;
 mod input;
 mod model;
+mod pairs;
+mod patch;
+mod plan;
 mod policy;
 mod replacer;
 mod reporter;
 mod rgjson;
+mod source;
+mod stream;
 mod transaction;
 mod write;
 
@@ -42,6 +49,22 @@ fn parse_range(s: &str) -> Option<LineRange> {
     Some(LineRange { start, end })
 }
 
+/// Parse `--nth`: `N`, `START:END`, `START:` or `:END`, where negative numbers count
+/// back from the last match.
+fn parse_occurrence(s: &str) -> Option<Occurrence> {
+    let (start, end) = match s.split_once(':') {
+        Some((start, end)) => (start, (!end.is_empty()).then_some(end)),
+        None => (s, Some(s)),
+    };
+    let start = if start.is_empty() {
+        1
+    } else {
+        start.parse().ok()?
+    };
+    let end = end.map(str::parse).transpose().ok()?;
+    Some(Occurrence { start, end })
+}
+
 fn resolve_permissions(args: &DefaultArgs) -> Result<Option<PermissionsMode>> {
     if let Some(ref m_str) = args.mode {
         let m = u32::from_str_radix(m_str, 8).context("Invalid octal mode")?;
@@ -57,6 +80,32 @@ fn resolve_permissions(args: &DefaultArgs) -> Result<Option<PermissionsMode>> {
     }
 }
 
+/// Gather the FIND/REPLACE pairs given with `-e`, `--pair` and `--pairs-file`, in the order
+/// they appear on the command line. The pairs of a pairs file keep the order of the file.
+fn collect_pairs(args: &DefaultArgs, matches: &ArgMatches) -> Result<Vec<pairs::Pair>> {
+    let indices = |id: &str| matches.indices_of(id).into_iter().flatten();
+    let mut all: Vec<(usize, pairs::Pair)> = Vec::new();
+    for (index, expr) in indices("expression").zip(&args.expression) {
+        all.push((index, pairs::parse_expression(expr)?));
+    }
+    // Each `--pair` has two values; the first one places it
+    for (index, pair) in indices("pair").step_by(2).zip(args.pair.chunks(2)) {
+        pairs::check_find(&pair[0], "--pair")?;
+        all.push((index, (pair[0].clone(), pair[1].clone())));
+    }
+    if let Some(path) = &args.pairs_file {
+        let index = matches.index_of("pairs_file").unwrap_or(usize::MAX);
+        all.extend(
+            pairs::read_pairs_file(path)?
+                .into_iter()
+                .map(|pair| (index, pair)),
+        );
+    }
+    // Stable, so a pairs file stays in file order
+    all.sort_by_key(|(index, _)| *index);
+    Ok(all.into_iter().map(|(_, pair)| pair).collect())
+}
+
 fn main() {
     match try_main() {
         Ok(code) => std::process::exit(code),
@@ -74,7 +123,8 @@ fn main() {
 }
 
 fn try_main() -> Result<i32> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     let (manifest_path, find, replace, files, default_args) = match cli.command {
         Some(Commands::Schema) => {
@@ -110,12 +160,28 @@ fn try_main() -> Result<i32> {
     // Determine the actual args to use, preferring manifest-specific overrides
     let args = default_args;
 
+    // With explicit pairs, every positional argument is a file
+    let pairs = collect_pairs(&args, &matches)?;
+    let (find, replace, files) = if pairs.is_empty() {
+        (find, replace, files)
+    } else {
+        let files = find
+            .into_iter()
+            .chain(replace)
+            .map(std::path::PathBuf::from)
+            .chain(files)
+            .collect();
+        (None, None, files)
+    };
+
     // Resolve input mode
     let mode = input::resolve_input_mode(
         args.stdin_paths,
         args.files0,
         args.stdin_text,
         args.rg_json,
+        args.patch.as_deref(),
+        args.walk.as_deref(),
         args.files_arg,
         &files,
     );
@@ -146,10 +212,31 @@ fn try_main() -> Result<i32> {
             vec![InputItem::StdinText(input::read_stdin_text()?)]
         }
         InputMode::RipgrepJson => input::read_rg_json()?,
+        InputMode::Patch(ref path) => input::read_patch(path, args.fuzz)?,
+        InputMode::Walk(ref root) => {
+            if !files.is_empty() {
+                bail!("--walk cannot be combined with FILE arguments");
+            }
+            let options = input::WalkOptions {
+                hidden: args.hidden,
+                no_ignore: args.no_ignore,
+                max_depth: args.max_depth,
+            };
+            input::walk_paths(root, options)?
+                .into_iter()
+                .map(InputItem::Path)
+                .collect()
+        }
     };
 
     // 2. Build Pipeline
     let pipeline = if let Some(path) = &manifest_path {
+        if let InputMode::Patch(_) = mode {
+            bail!("--patch cannot be combined with a manifest");
+        }
+        if !pairs.is_empty() {
+            bail!("-e/--pair/--pairs-file cannot be combined with a manifest");
+        }
         let content =
             fs::read_to_string(path).context(format!("reading manifest from {:?}", path))?;
         let mut p: Pipeline = serde_json::from_str(&content).context("parsing manifest")?;
@@ -182,6 +269,12 @@ fn try_main() -> Result<i32> {
         if let Some(b) = &args.binary {
             p.binary = b.clone().into();
         }
+        if let Some(e) = &args.encoding {
+            p.encoding = Some(e.clone().into());
+        }
+        if args.stream {
+            p.stream = true;
+        }
 
         // Resolve permissions override
         if let Some(perms) = resolve_permissions(&args)? {
@@ -198,31 +291,70 @@ fn try_main() -> Result<i32> {
         p
     } else {
         // Construct from CLI args (for default command)
-        let find = find.context("FIND pattern is required unless --manifest is used")?;
-        let replace = replace.context("REPLACE pattern is required unless --manifest is used")?;
-
-        let range = if let Some(r) = &args.range {
-            parse_range(r)
+        let operations = if let InputMode::Patch(_) = mode {
+            if find.is_some() || !pairs.is_empty() {
+                bail!("FIND/REPLACE cannot be combined with --patch");
+            }
+            vec![]
         } else {
-            None
-        };
+            let pairs = if pairs.is_empty() {
+                let find = find.context("FIND pattern is required unless --manifest is used")?;
+                let replace =
+                    replace.context("REPLACE pattern is required unless --manifest is used")?;
+                vec![(find, replace)]
+            } else {
+                pairs
+            };
 
-        let validation_mode = args.validation_mode.map(Into::into).unwrap_or_default();
-
-        let op = Operation::Replace {
-            find,
-            with: replace,
-            literal: !args.regex,
-            ignore_case: args.ignore_case,
-            smart_case: args.smart_case,
-            word: args.word_regexp,
-            multiline: args.multiline,
-            dot_matches_newline: args.dot_matches_newline,
-            no_unicode: args.no_unicode,
-            limit: args.limit.unwrap_or(0),
-            range,
-            expand: args.expand,
-            validation_mode,
+            let range = if let Some(r) = &args.range {
+                parse_range(r)
+            } else {
+                None
+            };
+
+            let occurrence = args
+                .nth
+                .as_deref()
+                .map(|nth| {
+                    parse_occurrence(nth).with_context(|| {
+                        format!(
+                            "Invalid --nth {:?}: expected N, START:END, START: or :END",
+                            nth
+                        )
+                    })
+                })
+                .transpose()?;
+
+            let validation_mode = args.validation_mode.map(Into::into).unwrap_or_default();
+
+            // Pairs are applied in order, each with the same match options
+            pairs
+                .into_iter()
+                .map(|(find, replace)| Operation::Replace {
+                    find,
+                    with: replace,
+                    literal: !args.regex,
+                    ignore_case: args.ignore_case,
+                    smart_case: args.smart_case,
+                    word: args.word_regexp,
+                    multiline: args.multiline,
+                    dot_matches_newline: args.dot_matches_newline,
+                    no_unicode: args.no_unicode,
+                    limit: args.limit.unwrap_or(0),
+                    range: range.clone(),
+                    occurrence,
+                    before: None,
+                    after: None,
+                    engine: args.engine.map(Into::into).unwrap_or_default(),
+                    backtrack_limit: args.backtrack_limit,
+                    whitespace: args.whitespace.map(Into::into).unwrap_or_default(),
+                    expect: None,
+                    expect_per_file: None,
+                    expand: args.expand,
+                    validation_mode,
+                    preserve_case: args.preserve_case,
+                })
+                .collect()
         };
 
         // Resolve permissions
@@ -230,7 +362,8 @@ fn try_main() -> Result<i32> {
 
         Pipeline {
             files: vec![], // Populated by inputs
-            operations: vec![op],
+            operations,
+            file_operations: vec![],
             dry_run: args.dry_run,
             no_write: args.no_write,
             require_match: args.require_match,
@@ -240,6 +373,8 @@ fn try_main() -> Result<i32> {
             symlinks: args.symlinks.clone().map(Into::into).unwrap_or_default(),
             binary: args.binary.clone().map(Into::into).unwrap_or_default(),
             permissions,
+            encoding: args.encoding.clone().map(Into::into),
+            stream: args.stream,
             validate_only: args.validate_only,
             glob_include: if args.glob_include.is_empty() {
                 None
@@ -254,6 +389,15 @@ fn try_main() -> Result<i32> {
         }
     };
 
+    if pipeline.stream
+        && matches!(
+            mode,
+            InputMode::StdinText | InputMode::RipgrepJson | InputMode::Patch(_)
+        )
+    {
+        bail!("--stream only applies to file inputs (not --stdin-text, --rg-json or --patch)");
+    }
+
     // Populate inputs from pipeline files if empty (common in apply mode)
     if inputs.is_empty() && !pipeline.files.is_empty() {
         for f in &pipeline.files {
@@ -289,6 +433,8 @@ fn try_main() -> Result<i32> {
         InputMode::StdinPathsNul => "files0",
         InputMode::StdinText => "stdin-text",
         InputMode::RipgrepJson => "rg-json",
+        InputMode::Patch(_) => "patch",
+        InputMode::Walk(_) => "walk",
     };
 
     match format {
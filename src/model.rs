@@ -1,5 +1,6 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LineRange {
@@ -7,6 +8,37 @@ pub struct LineRange {
     pub end: Option<usize>,
 }
 
+/// Which matches to act on, numbered from 1 in each file (`start..=end`).
+/// Negative numbers count back from the last match, so -1 is the last one.
+/// A missing `end` runs to the last match.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Occurrence {
+    pub start: i64,
+    pub end: Option<i64>,
+}
+
+impl Occurrence {
+    /// Whether the bounds depend on the number of matches.
+    pub fn counts_from_end(&self) -> bool {
+        self.start < 0 || self.end.is_some_and(|end| end < 0)
+    }
+
+    /// The selected match numbers as a 1-based inclusive range, given the number of
+    /// matches when `counts_from_end`. The range is empty if nothing is selected.
+    pub fn resolve(&self, total: usize) -> (usize, usize) {
+        let resolve = |n: i64| {
+            if n < 0 {
+                (total as i64 + 1 + n).max(0) as usize
+            } else {
+                n as usize
+            }
+        };
+        let start = resolve(self.start).max(1);
+        let end = self.end.map_or(usize::MAX, resolve);
+        (start, end)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ReplacementRange {
     pub start: usize,
@@ -38,6 +70,32 @@ pub enum BinaryFileMode {
     Error,
 }
 
+/// Text encoding of input files.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    /// ISO-8859-1.
+    #[serde(rename = "latin1", alias = "iso-8859-1")]
+    Latin1,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum PermissionsMode {
@@ -55,6 +113,65 @@ pub enum ValidationMode {
     None,
 }
 
+/// Regex engine for `find` patterns.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RegexEngine {
+    /// The `regex` crate: linear time, no lookaround or backreferences.
+    #[default]
+    Regex,
+    /// `fancy-regex`: adds lookaround and backreferences by backtracking.
+    /// Requires the `fancy` feature.
+    Fancy,
+}
+
+/// How whitespace in `find` is matched.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Whitespace {
+    /// Whitespace must match exactly.
+    #[default]
+    Exact,
+    /// `find` is taken literally, but any run of whitespace in it matches any run of
+    /// whitespace in the file. Leading whitespace of `find` is ignored, and line breaks at its
+    /// end match line breaks after any trailing whitespace. Requires `literal`.
+    Flexible,
+}
+
+/// Where `ensure_line` / `ensure_block` add content that is missing.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EnsurePosition {
+    /// At the start of the file.
+    Start,
+    /// At the end of the file.
+    #[default]
+    End,
+    /// On its own line(s) before the line containing the first anchor match.
+    Before,
+    /// On its own line(s) after the line containing the first anchor match.
+    After,
+}
+
+/// A line terminator.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
 /// A single text transformation operation.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case", tag = "type")]
@@ -92,12 +209,46 @@ pub enum Operation {
         /// Only apply replacements in a line range (1-based).
         #[serde(default)]
         range: Option<LineRange>,
+        /// Only replace these matches, e.g. the 2nd (`{"start": 2, "end": 2}`) or the last
+        /// (`{"start": -1}`). Counted after `range`; `limit` applies to the selection.
+        #[serde(default)]
+        occurrence: Option<Occurrence>,
+        /// Only replace matches immediately preceded by this pattern, which is not replaced.
+        /// Read like `find`, with the same `literal`, case, `multiline` and `no_unicode` flags.
+        #[serde(default)]
+        before: Option<String>,
+        /// Only replace matches immediately followed by this pattern, which is not replaced.
+        #[serde(default)]
+        after: Option<String>,
+        /// Regex engine for `find`.
+        #[serde(default)]
+        engine: RegexEngine,
+        /// Backtracking steps the `fancy` engine may take per search before failing the file.
+        #[serde(default)]
+        backtrack_limit: Option<usize>,
+        /// How whitespace in `find` is matched. With `flexible` (which requires `literal`), lines
+        /// of `with` after the first are reindented to the indentation of the matched text.
+        #[serde(default)]
+        whitespace: Whitespace,
+        /// Fail the run unless `find` matches exactly this many times across all files.
+        /// Matches are counted after `range`, `before` and `after`, but before `occurrence`
+        /// and `limit`.
+        #[serde(default)]
+        expect: Option<usize>,
+        /// Fail the run unless `find` matches exactly this many times in every file,
+        /// e.g. 1 to require a unique match. Counted like `expect`.
+        #[serde(default)]
+        expect_per_file: Option<usize>,
         /// Enable regex capture expansion (e.g. $1, $name).
         #[serde(default)]
         expand: bool,
         /// Replacement validation mode.
         #[serde(default)]
         validation_mode: ValidationMode,
+        /// Also replace the snake, SCREAMING, kebab, camel and Pascal case spellings of
+        /// `find`, each with `with` in the same convention. `find` is taken literally.
+        #[serde(default)]
+        preserve_case: bool,
     },
     /// Delete occurrences of a pattern.
     Delete {
@@ -130,8 +281,290 @@ pub enum Operation {
         /// Only apply replacements in a line range (1-based).
         #[serde(default)]
         range: Option<LineRange>,
+        /// Only delete these matches (see `Replace`).
+        #[serde(default)]
+        occurrence: Option<Occurrence>,
+        /// Regex engine for `find`.
+        #[serde(default)]
+        engine: RegexEngine,
+        /// Backtracking steps the `fancy` engine may take per search before failing the file.
+        #[serde(default)]
+        backtrack_limit: Option<usize>,
+        /// How whitespace in `find` is matched.
+        #[serde(default)]
+        whitespace: Whitespace,
+        /// Fail the run unless `find` matches exactly this many times across all files.
+        #[serde(default)]
+        expect: Option<usize>,
+        /// Fail the run unless `find` matches exactly this many times in every file.
+        #[serde(default)]
+        expect_per_file: Option<usize>,
+    },
+    /// Insert text immediately before each match of a pattern.
+    InsertBefore {
+        /// Pattern to anchor on (literal string or regex).
+        find: String,
+        /// Text to insert.
+        text: String,
+        /// Whether to treat pattern as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Smart-case: case-insensitive if pattern is all lowercase.
+        #[serde(default)]
+        smart_case: bool,
+        /// Match only at word boundaries.
+        #[serde(default)]
+        word: bool,
+        /// Enable multi-line mode (^ and $ match line boundaries).
+        #[serde(default)]
+        multiline: bool,
+        /// Make '.' match newlines.
+        #[serde(default)]
+        dot_matches_newline: bool,
+        /// Disable Unicode-aware matching.
+        #[serde(default)]
+        no_unicode: bool,
+        /// Maximum number of insertions per file (0 = unlimited).
+        #[serde(default)]
+        limit: usize,
+        /// Only insert at matches in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Insert text immediately after each match of a pattern.
+    InsertAfter {
+        /// Pattern to anchor on (literal string or regex).
+        find: String,
+        /// Text to insert.
+        text: String,
+        /// Whether to treat pattern as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Smart-case: case-insensitive if pattern is all lowercase.
+        #[serde(default)]
+        smart_case: bool,
+        /// Match only at word boundaries.
+        #[serde(default)]
+        word: bool,
+        /// Enable multi-line mode (^ and $ match line boundaries).
+        #[serde(default)]
+        multiline: bool,
+        /// Make '.' match newlines.
+        #[serde(default)]
+        dot_matches_newline: bool,
+        /// Disable Unicode-aware matching.
+        #[serde(default)]
+        no_unicode: bool,
+        /// Maximum number of insertions per file (0 = unlimited).
+        #[serde(default)]
+        limit: usize,
+        /// Only insert at matches in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Delete every line that matches a pattern.
+    DeleteLines {
+        /// Pattern matched against each line (literal string or regex).
+        find: String,
+        /// Whether to treat pattern as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Smart-case: case-insensitive if pattern is all lowercase.
+        #[serde(default)]
+        smart_case: bool,
+        /// Match only at word boundaries.
+        #[serde(default)]
+        word: bool,
+        /// Disable Unicode-aware matching.
+        #[serde(default)]
+        no_unicode: bool,
+        /// Maximum number of lines affected per file (0 = unlimited).
+        #[serde(default)]
+        limit: usize,
+        /// Only consider lines in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Delete every line that does not match a pattern.
+    KeepLines {
+        /// Pattern matched against each line (literal string or regex).
+        find: String,
+        /// Whether to treat pattern as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Smart-case: case-insensitive if pattern is all lowercase.
+        #[serde(default)]
+        smart_case: bool,
+        /// Match only at word boundaries.
+        #[serde(default)]
+        word: bool,
+        /// Disable Unicode-aware matching.
+        #[serde(default)]
+        no_unicode: bool,
+        /// Only consider lines in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Replace each matching line as a whole, keeping its line ending.
+    ReplaceLine {
+        /// Pattern matched against each line (literal string or regex).
+        find: String,
+        /// Replacement for the whole line (without its line ending).
+        with: String,
+        /// Whether to treat pattern as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Smart-case: case-insensitive if pattern is all lowercase.
+        #[serde(default)]
+        smart_case: bool,
+        /// Match only at word boundaries.
+        #[serde(default)]
+        word: bool,
+        /// Disable Unicode-aware matching.
+        #[serde(default)]
+        no_unicode: bool,
+        /// Maximum number of lines affected per file (0 = unlimited).
+        #[serde(default)]
+        limit: usize,
+        /// Only consider lines in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Replace everything between a start marker and an end marker.
+    ReplaceBlock {
+        /// Pattern marking the start of a block (literal string or regex).
+        start: String,
+        /// Pattern marking the end of a block (literal string or regex).
+        end: String,
+        /// Replacement text for each block.
+        with: String,
+        /// Replace the markers too, instead of only the text between them.
+        #[serde(default)]
+        include_markers: bool,
+        /// Whether to treat markers as literal strings (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Smart-case: case-insensitive if pattern is all lowercase.
+        #[serde(default)]
+        smart_case: bool,
+        /// Enable multi-line mode (^ and $ match line boundaries).
+        #[serde(default)]
+        multiline: bool,
+        /// Disable Unicode-aware matching.
+        #[serde(default)]
+        no_unicode: bool,
+        /// Maximum number of blocks replaced per file (0 = unlimited).
+        #[serde(default)]
+        limit: usize,
+        /// Only replace blocks starting in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Add a line unless a line with exactly this content already exists.
+    EnsureLine {
+        /// The line that must be present (without line ending).
+        line: String,
+        /// Where to add the content if it is missing.
+        #[serde(default)]
+        position: EnsurePosition,
+        /// Anchor pattern, required for the `before` and `after` positions.
+        #[serde(default)]
+        anchor: Option<String>,
+        /// Whether to treat the anchor as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive anchor matching.
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    /// Add a block of text unless it already occurs in the file.
+    EnsureBlock {
+        /// The text that must be present.
+        block: String,
+        /// Where to add the content if it is missing.
+        #[serde(default)]
+        position: EnsurePosition,
+        /// Anchor pattern, required for the `before` and `after` positions.
+        #[serde(default)]
+        anchor: Option<String>,
+        /// Whether to treat the anchor as literal string (not regex).
+        #[serde(default)]
+        literal: bool,
+        /// Case-insensitive anchor matching.
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    /// Replace several literal strings in one simultaneous pass.
+    /// The longest key wins at each position and replaced text is not matched again,
+    /// so entries can swap strings.
+    ReplaceMap {
+        /// Literal key to replacement text.
+        map: BTreeMap<String, String>,
+        /// ASCII case-insensitive matching.
+        #[serde(default)]
+        ignore_case: bool,
+        /// Maximum number of replacements per file across all keys (0 = unlimited).
+        #[serde(default)]
+        limit: usize,
+        /// Only apply replacements in a line range (1-based).
+        #[serde(default)]
+        range: Option<LineRange>,
+    },
+    /// Convert every line ending in the file to `eol`.
+    NormalizeEol {
+        /// The line ending to use.
+        eol: LineEnding,
+    },
+}
+
+/// A filesystem-level operation, committed in the same transaction as content edits.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum FileOperation {
+    /// Create a file with the given content, along with any missing parent directories.
+    Create {
+        /// Path of the file to create.
+        path: String,
+        /// Content of the new file.
+        #[serde(default)]
+        content: String,
+        /// Replace the file if it already exists.
+        #[serde(default)]
+        overwrite: bool,
+    },
+    /// Delete an existing file (not a directory).
+    Delete {
+        /// Path of the file to delete.
+        path: String,
+    },
+    /// Rename or move a file.
+    Rename {
+        /// Current path of the file.
+        from: String,
+        /// New path of the file.
+        to: String,
+        /// Replace the destination if it already exists.
+        #[serde(default)]
+        overwrite: bool,
     },
-    // Future operations: Insert, RegexReplace, etc.
 }
 
 /// A complete transformation pipeline.
@@ -141,6 +574,10 @@ pub struct Pipeline {
     pub files: Vec<String>,
     /// Operations to apply to each file.
     pub operations: Vec<Operation>,
+    /// File-level operations (create, delete, rename), applied after content edits.
+    /// `files` must not include a path they create or rename onto.
+    #[serde(default)]
+    pub file_operations: Vec<FileOperation>,
 
     // Safety and guarantees
     /// Dry-run mode: compute changes but don't write.
@@ -170,6 +607,14 @@ pub struct Pipeline {
     pub binary: BinaryFileMode,
     #[serde(default)]
     pub permissions: PermissionsMode,
+    /// Encoding of input files. Detected from the BOM (falling back to UTF-8) if unset.
+    #[serde(default)]
+    pub encoding: Option<Encoding>,
+
+    /// Process files one line at a time with bounded memory.
+    /// Only line-bounded operations are allowed.
+    #[serde(default)]
+    pub stream: bool,
 
     /// Validate manifest and semantic checks without running.
     #[serde(default)]
@@ -201,9 +646,19 @@ impl Pipeline {
                 no_unicode: false,
                 limit: 0,
                 range: None,
+                occurrence: None,
+                before: None,
+                after: None,
+                engine: RegexEngine::default(),
+                backtrack_limit: None,
+                whitespace: Whitespace::default(),
+                expect: None,
+                expect_per_file: None,
                 expand: false,
                 validation_mode: ValidationMode::default(),
+                preserve_case: false,
             }],
+            file_operations: vec![],
             dry_run: false,
             no_write: false,
             require_match: false,
@@ -213,6 +668,8 @@ impl Pipeline {
             symlinks: Symlinks::default(),
             binary: BinaryFileMode::default(),
             permissions: PermissionsMode::default(),
+            encoding: None,
+            stream: false,
             validate_only: false,
             glob_include: None,
             glob_exclude: None,
@@ -248,6 +705,17 @@ impl From<crate::cli::BinaryFileMode> for BinaryFileMode {
     }
 }
 
+impl From<crate::cli::Encoding> for Encoding {
+    fn from(item: crate::cli::Encoding) -> Self {
+        match item {
+            crate::cli::Encoding::Utf8 => Encoding::Utf8,
+            crate::cli::Encoding::Utf16Le => Encoding::Utf16Le,
+            crate::cli::Encoding::Utf16Be => Encoding::Utf16Be,
+            crate::cli::Encoding::Latin1 => Encoding::Latin1,
+        }
+    }
+}
+
 impl From<crate::cli::ValidationMode> for ValidationMode {
     fn from(item: crate::cli::ValidationMode) -> Self {
         match item {
@@ -257,3 +725,21 @@ impl From<crate::cli::ValidationMode> for ValidationMode {
         }
     }
 }
+
+impl From<crate::cli::Whitespace> for Whitespace {
+    fn from(item: crate::cli::Whitespace) -> Self {
+        match item {
+            crate::cli::Whitespace::Exact => Whitespace::Exact,
+            crate::cli::Whitespace::Flexible => Whitespace::Flexible,
+        }
+    }
+}
+
+impl From<crate::cli::RegexEngine> for RegexEngine {
+    fn from(item: crate::cli::RegexEngine) -> Self {
+        match item {
+            crate::cli::RegexEngine::Regex => RegexEngine::Regex,
+            crate::cli::RegexEngine::Fancy => RegexEngine::Fancy,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::error::{Error, Result};
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+/// One FIND/REPLACE pair from the command line or a pairs file.
+pub type Pair = (String, String);
+
+/// Parse a `-e FIND=REPLACE` expression. FIND ends at the first `=`.
+pub fn parse_expression(expr: &str) -> Result<Pair> {
+    let (find, replace) = expr.split_once('=').ok_or_else(|| {
+        Error::Validation(format!(
+            "Invalid expression {:?}: expected FIND=REPLACE",
+            expr
+        ))
+    })?;
+    check_find(find, expr)?;
+    Ok((find.to_string(), replace.to_string()))
+}
+
+/// Read a mapping table. `.json` files hold an object (`{"FIND": "REPLACE"}`) or an array of
+/// `[FIND, REPLACE]` pairs; anything else is read as one tab-separated pair per line.
+/// Pairs keep the order of the file.
+pub fn read_pairs_file(path: &Path) -> Result<Vec<Pair>> {
+    let text = std::fs::read_to_string(path).map_err(Error::Io)?;
+    let pairs = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str::<OrderedPairs>(&text)
+            .map_err(|e| Error::Validation(format!("Invalid pairs file {:?}: {}", path, e)))?
+            .0
+    } else {
+        parse_tsv(&text).map_err(|e| match e {
+            Error::Validation(msg) => Error::Validation(format!("{:?}: {}", path, msg)),
+            e => e,
+        })?
+    };
+    for (find, _) in &pairs {
+        check_find(find, &path.display().to_string())?;
+    }
+    Ok(pairs)
+}
+
+fn parse_tsv(text: &str) -> Result<Vec<Pair>> {
+    let mut pairs = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let (find, replace) = line.split_once('\t').ok_or_else(|| {
+            Error::Validation(format!("line {}: expected FIND<TAB>REPLACE", index + 1))
+        })?;
+        pairs.push((find.to_string(), replace.to_string()));
+    }
+    Ok(pairs)
+}
+
+/// Reject an empty FIND, which would match everywhere.
+pub fn check_find(find: &str, source: &str) -> Result<()> {
+    if find.is_empty() {
+        return Err(Error::Validation(format!("Empty FIND in {:?}", source)));
+    }
+    Ok(())
+}
+
+/// A JSON object or array of pairs, in document order.
+struct OrderedPairs(Vec<Pair>);
+
+impl<'de> Deserialize<'de> for OrderedPairs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PairsVisitor;
+
+        impl<'de> Visitor<'de> for PairsVisitor {
+            type Value = OrderedPairs;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an object of FIND: REPLACE or an array of [FIND, REPLACE] pairs")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut pairs = Vec::new();
+                while let Some(pair) = map.next_entry()? {
+                    pairs.push(pair);
+                }
+                Ok(OrderedPairs(pairs))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut pairs = Vec::new();
+                while let Some(pair) = seq.next_element()? {
+                    pairs.push(pair);
+                }
+                Ok(OrderedPairs(pairs))
+            }
+        }
+
+        deserializer.deserialize_any(PairsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<Pair> {
+        items
+            .iter()
+            .map(|(f, r)| (f.to_string(), r.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expression_splits_at_first_equals() {
+        assert_eq!(
+            parse_expression("a=b=c").unwrap(),
+            ("a".to_string(), "b=c".to_string())
+        );
+        assert_eq!(
+            parse_expression("old=").unwrap(),
+            ("old".to_string(), String::new())
+        );
+        assert!(parse_expression("noequals").is_err());
+        assert!(parse_expression("=x").is_err());
+    }
+
+    #[test]
+    fn pairs_files_keep_their_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let tsv = dir.path().join("pairs.tsv");
+        std::fs::write(&tsv, "zeta\tomega\r\n\nalpha\tbeta\tgamma\n").unwrap();
+        assert_eq!(
+            read_pairs_file(&tsv).unwrap(),
+            pairs(&[("zeta", "omega"), ("alpha", "beta\tgamma")])
+        );
+
+        let object = dir.path().join("object.json");
+        std::fs::write(&object, r#"{"zeta": "omega", "alpha": "beta"}"#).unwrap();
+        assert_eq!(
+            read_pairs_file(&object).unwrap(),
+            pairs(&[("zeta", "omega"), ("alpha", "beta")])
+        );
+
+        let array = dir.path().join("array.json");
+        std::fs::write(&array, r#"[["zeta", "omega"], ["alpha", "beta"]]"#).unwrap();
+        assert_eq!(
+            read_pairs_file(&array).unwrap(),
+            pairs(&[("zeta", "omega"), ("alpha", "beta")])
+        );
+    }
+
+    #[test]
+    fn malformed_tsv_reports_the_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let tsv = dir.path().join("pairs.tsv");
+        std::fs::write(&tsv, "a\tb\nbroken\n").unwrap();
+        let err = read_pairs_file(&tsv).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+}
@@ -0,0 +1,691 @@
+use crate::error::{Error, Result};
+use std::iter;
+use std::path::{Component, Path, PathBuf};
+
+/// All hunks of a unified diff that target one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePatch {
+    /// Path on the `---` side, `None` for `/dev/null` (file creation).
+    pub old_path: Option<PathBuf>,
+    /// Path on the `+++` side, `None` for `/dev/null` (file deletion).
+    pub new_path: Option<PathBuf>,
+    /// Set by git's `rename from`/`rename to` headers: `old_path` is moved to `new_path`.
+    pub renamed: bool,
+    pub hunks: Vec<Hunk>,
+    /// Where each later section for the same file starts in `hunks`.
+    /// A section applies to the result of the ones before it.
+    pub section_starts: Vec<usize>,
+}
+
+impl FilePatch {
+    /// The file this patch writes.
+    pub fn target(&self) -> &Path {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .expect("a file patch has at least one path")
+    }
+
+    /// The file this patch reads, which differs from `target` for a rename.
+    pub fn source(&self) -> &Path {
+        match &self.old_path {
+            Some(old) if self.renamed => old,
+            _ => self.target(),
+        }
+    }
+
+    pub fn is_creation(&self) -> bool {
+        self.old_path.is_none()
+    }
+
+    pub fn is_deletion(&self) -> bool {
+        self.new_path.is_none()
+    }
+
+    pub fn is_rename(&self) -> bool {
+        self.renamed
+    }
+
+    /// The hunks of each section, in order.
+    pub fn sections(&self) -> impl Iterator<Item = &[Hunk]> {
+        let starts = iter::once(0).chain(self.section_starts.iter().copied());
+        let ends = self
+            .section_starts
+            .iter()
+            .copied()
+            .chain(iter::once(self.hunks.len()));
+        starts.zip(ends).map(|(start, end)| &self.hunks[start..end])
+    }
+
+    /// Apply every section in turn. Rejected hunks are numbered across all sections.
+    pub fn apply(&self, original: &[u8], fuzz: usize) -> PatchOutcome {
+        let mut sections = self.sections();
+        let first = sections.next().unwrap_or_default();
+        let mut outcome = apply_hunks(original, first, fuzz);
+        let mut offset = first.len();
+        for hunks in sections {
+            let next = apply_hunks(&outcome.content, hunks, fuzz);
+            outcome.content = next.content;
+            outcome.applied += next.applied;
+            outcome
+                .rejected
+                .extend(next.rejected.into_iter().map(|r| Rejection {
+                    hunk: r.hunk + offset,
+                    ..r
+                }));
+            offset += hunks.len();
+        }
+        outcome
+    }
+
+    /// Paths this patch reads or writes.
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        let source = self.is_rename().then(|| self.source());
+        source.into_iter().chain(iter::once(self.target()))
+    }
+}
+
+/// A single `@@ -a,b +c,d @@` hunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<HunkLine>,
+    /// The old side ends without a trailing newline.
+    pub old_no_eol: bool,
+    /// The new side ends without a trailing newline.
+    pub new_no_eol: bool,
+}
+
+/// One line of a hunk body, without its `\n` terminator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+impl Hunk {
+    fn old_lines(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_bytes()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    fn new_lines(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Add(s) => Some(s.as_bytes()),
+                HunkLine::Remove(_) => None,
+            })
+            .collect()
+    }
+
+    /// Number of context lines before the first and after the last change.
+    fn context_bounds(&self) -> (usize, usize) {
+        let is_context = |l: &&HunkLine| matches!(l, HunkLine::Context(_));
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = if leading == self.lines.len() {
+            0
+        } else {
+            self.lines.iter().rev().take_while(is_context).count()
+        };
+        (leading, trailing)
+    }
+}
+
+/// A hunk that could not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    /// 1-based index of the hunk within its file.
+    pub hunk: usize,
+    /// Line the hunk was expected at in the original file.
+    pub line: usize,
+}
+
+/// Result of applying the hunks of one file.
+#[derive(Debug)]
+pub struct PatchOutcome {
+    pub content: Vec<u8>,
+    pub applied: usize,
+    pub rejected: Vec<Rejection>,
+}
+
+/// Parse a unified diff (as produced by `diff -u` or `git diff`).
+///
+/// Lines outside of file headers and hunks (`diff --git`, `index`, commit messages) are ignored,
+/// except for git's `rename from`/`rename to`. Sections that patch the same file again are
+/// merged into one `FilePatch`.
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
+    // The patch that hunks are added to
+    let mut current = None;
+    let mut rename_from = None;
+    // The `---`/`+++` header of this git section repeats its rename headers
+    let mut renamed = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if line.starts_with("diff --git ") {
+            current = None;
+            rename_from = None;
+            renamed = false;
+            i += 1;
+        } else if let Some(from) = line.strip_prefix("rename from ") {
+            rename_from = Some(PathBuf::from(from.trim_end_matches('\r')));
+            i += 1;
+        } else if let Some(to) = line.strip_prefix("rename to ") {
+            let Some(from) = rename_from.take() else {
+                return Err(invalid(i, "`rename to` without `rename from`"));
+            };
+            let patch = FilePatch {
+                old_path: Some(from),
+                new_path: Some(PathBuf::from(to.trim_end_matches('\r'))),
+                renamed: true,
+                hunks: Vec::new(),
+                section_starts: Vec::new(),
+            };
+            current = Some(add_section(&mut patches, patch, i)?);
+            renamed = true;
+            i += 1;
+        } else if line.starts_with("--- ")
+            && i + 1 < lines.len()
+            && lines[i + 1].starts_with("+++ ")
+        {
+            if !renamed {
+                let old = parse_header_path(&line[4..]);
+                let new = parse_header_path(&lines[i + 1][4..]);
+                if old.is_none() && new.is_none() {
+                    return Err(invalid(i, "both sides of the file header are /dev/null"));
+                }
+                let (old_path, new_path) = strip_git_prefixes(old, new);
+                let patch = FilePatch {
+                    old_path,
+                    new_path,
+                    renamed: false,
+                    hunks: Vec::new(),
+                    section_starts: Vec::new(),
+                };
+                current = Some(add_section(&mut patches, patch, i)?);
+            }
+            renamed = false;
+            i += 2;
+        } else if line.starts_with("@@ ") {
+            let Some(current) = current else {
+                return Err(invalid(i, "hunk without a file header"));
+            };
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            patches[current].hunks.push(hunk);
+            i = next;
+        } else {
+            i += 1;
+        }
+    }
+
+    if patches.is_empty() {
+        return Err(Error::Validation(
+            "Invalid patch: no file headers found".into(),
+        ));
+    }
+    Ok(patches)
+}
+
+/// Add the section whose header is at line `index`, and return the patch its hunks go to.
+///
+/// A section that reads the file an earlier one writes continues that patch.
+/// Paths must stay inside the working directory.
+fn add_section(patches: &mut Vec<FilePatch>, patch: FilePatch, index: usize) -> Result<usize> {
+    for path in patch.paths() {
+        let escapes = path.components().any(|c| {
+            matches!(
+                c,
+                Component::ParentDir | Component::RootDir | Component::Prefix(_)
+            )
+        });
+        if escapes {
+            let msg = format!("{} is outside the working directory", path.display());
+            return Err(invalid(index, &msg));
+        }
+    }
+
+    let earlier = patches.iter().position(|p| p.target() == patch.source());
+    for (n, other) in patches.iter().enumerate() {
+        if Some(n) == earlier {
+            continue;
+        }
+        if let Some(path) = patch.paths().find(|&path| other.paths().any(|p| p == path)) {
+            let msg = format!("{} is already patched by another section", path.display());
+            return Err(invalid(index, &msg));
+        }
+    }
+
+    let Some(n) = earlier else {
+        patches.push(patch);
+        return Ok(patches.len() - 1);
+    };
+    let merged = &mut patches[n];
+    merged.new_path = patch.new_path;
+    merged.renamed = (merged.renamed || patch.renamed) && merged.old_path != merged.new_path;
+    merged.section_starts.push(merged.hunks.len());
+    Ok(n)
+}
+
+fn invalid(index: usize, msg: &str) -> Error {
+    Error::Validation(format!("Invalid patch at line {}: {}", index + 1, msg))
+}
+
+/// Extract the path from a `---`/`+++` header, dropping any trailing timestamp.
+fn parse_header_path(raw: &str) -> Option<String> {
+    let path = raw.split('\t').next().unwrap_or(raw).trim_end_matches('\r');
+    let path = path.trim_end();
+    if path == "/dev/null" {
+        None
+    } else {
+        Some(path.to_string())
+    }
+}
+
+/// Strip git's `a/` and `b/` prefixes when every present side carries them.
+fn strip_git_prefixes(
+    old: Option<String>,
+    new: Option<String>,
+) -> (Option<PathBuf>, Option<PathBuf>) {
+    let git_style = old.as_deref().is_none_or(|p| p.starts_with("a/"))
+        && new.as_deref().is_none_or(|p| p.starts_with("b/"));
+    let convert = |p: Option<String>| {
+        p.map(|p| {
+            if git_style {
+                PathBuf::from(&p[2..])
+            } else {
+                PathBuf::from(p)
+            }
+        })
+    };
+    (convert(old), convert(new))
+}
+
+/// Parse a `-a,b` or `+c,d` range. The length defaults to 1.
+fn parse_range(raw: &str) -> Option<(usize, usize)> {
+    match raw.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((raw.parse().ok()?, 1)),
+    }
+}
+
+fn parse_hunk(lines: &[&str], start: usize) -> Result<(Hunk, usize)> {
+    let header = lines[start];
+    let ranges = header[3..]
+        .split(" @@")
+        .next()
+        .ok_or_else(|| invalid(start, "malformed hunk header"))?;
+    let mut parts = ranges.split_whitespace();
+    let (old_start, old_len) = parts
+        .next()
+        .and_then(|p| p.strip_prefix('-'))
+        .and_then(parse_range)
+        .ok_or_else(|| invalid(start, "malformed hunk header"))?;
+    let (new_start, new_len) = parts
+        .next()
+        .and_then(|p| p.strip_prefix('+'))
+        .and_then(parse_range)
+        .ok_or_else(|| invalid(start, "malformed hunk header"))?;
+
+    let mut hunk = Hunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: Vec::new(),
+        old_no_eol: false,
+        new_no_eol: false,
+    };
+
+    let (mut old_seen, mut new_seen) = (0, 0);
+    let mut i = start + 1;
+    while old_seen < old_len || new_seen < new_len {
+        // The empty string after the final newline is not a line.
+        let Some(&line) = lines
+            .get(i)
+            .filter(|l| !l.is_empty() || i + 1 < lines.len())
+        else {
+            return Err(invalid(start, "hunk is truncated"));
+        };
+        // Some tools strip the single space of empty context lines.
+        let (tag, body) = match line.chars().next() {
+            Some(c) => (c, &line[c.len_utf8()..]),
+            None => (' ', ""),
+        };
+        match tag {
+            ' ' => {
+                hunk.lines.push(HunkLine::Context(body.to_string()));
+                old_seen += 1;
+                new_seen += 1;
+            }
+            '-' => {
+                hunk.lines.push(HunkLine::Remove(body.to_string()));
+                old_seen += 1;
+            }
+            '+' => {
+                hunk.lines.push(HunkLine::Add(body.to_string()));
+                new_seen += 1;
+            }
+            '\\' if i > start + 1 => mark_no_eol(&mut hunk, lines[i - 1]),
+            _ => return Err(invalid(i, "unexpected line inside hunk")),
+        }
+        i += 1;
+    }
+    if old_seen != old_len || new_seen != new_len {
+        return Err(invalid(start, "hunk line counts do not match its header"));
+    }
+
+    // "\ No newline at end of file" marks the line before it.
+    while lines.get(i).is_some_and(|l| l.starts_with('\\')) {
+        mark_no_eol(&mut hunk, lines[i - 1]);
+        i += 1;
+    }
+
+    Ok((hunk, i))
+}
+
+fn mark_no_eol(hunk: &mut Hunk, previous: &str) {
+    match previous.chars().next() {
+        Some('-') => hunk.old_no_eol = true,
+        Some('+') => hunk.new_no_eol = true,
+        Some('\\') => {}
+        _ => {
+            hunk.old_no_eol = true;
+            hunk.new_no_eol = true;
+        }
+    }
+}
+
+/// Apply `hunks` to `original`.
+///
+/// Each hunk is first tried at its recorded line, then at increasing offsets from it.
+/// With `fuzz` > 0, up to that many leading and trailing context lines may be ignored.
+/// Rejected hunks leave the content untouched and are listed in the outcome.
+/// Lines are compared as bytes, so content that is not valid UTF-8 is carried over unchanged.
+pub fn apply_hunks(original: &[u8], hunks: &[Hunk], fuzz: usize) -> PatchOutcome {
+    let mut lines: Vec<&[u8]> = if original.is_empty() {
+        Vec::new()
+    } else {
+        original.split(|&b| b == b'\n').collect()
+    };
+    let mut eol = true;
+    if !lines.is_empty() {
+        if original.ends_with(b"\n") {
+            lines.pop();
+        } else {
+            eol = false;
+        }
+    }
+
+    let mut applied = 0;
+    let mut rejected = Vec::new();
+    // Difference between where the previous hunk landed and where its header said it would.
+    let mut shift: isize = 0;
+    let mut min_pos = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let old = hunk.old_lines();
+        let new = hunk.new_lines();
+        let (leading, trailing) = hunk.context_bounds();
+        let origin = if hunk.old_len == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+
+        let mut placed = None;
+        for f in 0..=fuzz {
+            let (lead, trail) = (f.min(leading), f.min(trailing));
+            if f > 0 && lead == leading.min(f - 1) && trail == trailing.min(f - 1) {
+                break;
+            }
+            let old_part = &old[lead..old.len() - trail];
+            let expected = (origin + lead) as isize + shift;
+            if let Some(pos) = find_position(&lines, old_part, expected, min_pos) {
+                placed = Some((pos, lead, trail));
+                break;
+            }
+        }
+
+        let Some((pos, lead, trail)) = placed else {
+            rejected.push(Rejection {
+                hunk: index + 1,
+                line: hunk.old_start,
+            });
+            continue;
+        };
+
+        let old_part_len = old.len() - lead - trail;
+        let new_part = &new[lead..new.len() - trail];
+        let reaches_end = trail == 0 && pos + old_part_len == lines.len();
+        lines.splice(pos..pos + old_part_len, new_part.iter().copied());
+
+        if reaches_end {
+            if hunk.new_no_eol {
+                eol = false;
+            } else if hunk.old_no_eol || !new_part.is_empty() {
+                eol = true;
+            }
+        }
+
+        shift = pos as isize - (origin + lead) as isize + new_part.len() as isize
+            - old_part_len as isize;
+        min_pos = pos + new_part.len();
+        applied += 1;
+    }
+
+    let mut content = lines.join(&b'\n');
+    if eol && !lines.is_empty() {
+        content.push(b'\n');
+    }
+
+    PatchOutcome {
+        content,
+        applied,
+        rejected,
+    }
+}
+
+/// Find `needle` in `lines`, starting at `expected` and searching outwards.
+fn find_position(
+    lines: &[&[u8]],
+    needle: &[&[u8]],
+    expected: isize,
+    min_pos: usize,
+) -> Option<usize> {
+    if lines.len() < needle.len() {
+        return None;
+    }
+    let max_pos = lines.len() - needle.len();
+    if min_pos > max_pos {
+        return None;
+    }
+    let expected = expected.clamp(min_pos as isize, max_pos as isize) as usize;
+    let matches = |pos: usize| lines[pos..pos + needle.len()] == *needle;
+
+    for distance in 0..=(max_pos - min_pos) {
+        if let Some(pos) = expected.checked_add(distance).filter(|&p| p <= max_pos) {
+            if matches(pos) {
+                return Some(pos);
+            }
+        }
+        if distance > 0 {
+            if let Some(pos) = expected.checked_sub(distance).filter(|&p| p >= min_pos) {
+                if matches(pos) {
+                    return Some(pos);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+@@ -6,2 +6,3 @@
+ six
+ seven
++eight
+";
+
+    #[test]
+    fn parses_git_style_headers_and_hunks() {
+        let patches = parse_patch(PATCH).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].target(), Path::new("src/lib.rs"));
+        assert_eq!(patches[0].hunks.len(), 2);
+        assert_eq!(patches[0].hunks[1].old_start, 6);
+        assert_eq!(patches[0].hunks[0].lines[1], HunkLine::Remove("two".into()));
+    }
+
+    #[test]
+    fn parses_creation_and_deletion() {
+        let patch = "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+hello\n\
+                     --- a/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n";
+        let patches = parse_patch(patch).unwrap();
+        assert!(patches[0].is_creation());
+        assert_eq!(patches[0].target(), Path::new("new.txt"));
+        assert!(patches[1].is_deletion());
+        assert_eq!(patches[1].target(), Path::new("old.txt"));
+    }
+
+    #[test]
+    fn parses_git_renames() {
+        let patch = "diff --git a/old.rs b/new.rs\nrename from old.rs\nrename to new.rs\n\
+                     --- a/old.rs\n+++ b/new.rs\n@@ -1 +1 @@\n-a\n+b\n\
+                     diff --git a/x.rs b/y/x.rs\nsimilarity index 100%\n\
+                     rename from x.rs\nrename to y/x.rs\n";
+        let patches = parse_patch(patch).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert!(patches[0].is_rename());
+        assert_eq!(patches[0].source(), Path::new("old.rs"));
+        assert_eq!(patches[0].target(), Path::new("new.rs"));
+        assert_eq!(patches[0].hunks.len(), 1);
+        assert!(patches[1].is_rename());
+        assert!(patches[1].hunks.is_empty());
+        assert_eq!(patches[1].target(), Path::new("y/x.rs"));
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_working_directory() {
+        for header in ["--- a/../x\n+++ b/../x\n", "--- /etc/x\n+++ /etc/x\n"] {
+            let err = parse_patch(&format!("{header}@@ -1 +1 @@\n-a\n+b\n")).unwrap_err();
+            assert!(err.to_string().contains("outside the working directory"));
+        }
+    }
+
+    #[test]
+    fn merges_repeated_files_into_sections() {
+        let patch = "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+b\n\
+                     --- a/g\n+++ b/g\n@@ -1 +1 @@\n-x\n+y\n\
+                     --- a/f\n+++ b/f\n@@ -1 +1 @@\n-b\n+c\n";
+        let patches = parse_patch(patch).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].sections().count(), 2);
+
+        // The second section applies to the result of the first
+        let outcome = patches[0].apply(b"a\n", 0);
+        assert_eq!(outcome.content, b"c\n");
+        assert_eq!(outcome.applied, 2);
+
+        let outcome = patches[0].apply(b"z\n", 0);
+        let hunks: Vec<usize> = outcome.rejected.iter().map(|r| r.hunk).collect();
+        assert_eq!(hunks, vec![1, 2]);
+    }
+
+    #[test]
+    fn rejects_unrelated_sections_for_one_file() {
+        // `f` no longer exists once it is renamed
+        let patch = "diff --git a/f b/g\nrename from f\nrename to g\n\
+                     diff --git a/f b/f\n--- a/f\n+++ b/f\n@@ -1 +1 @@\n-x\n+y\n";
+        let err = parse_patch(patch).unwrap_err();
+        assert!(err.to_string().contains("already patched"));
+    }
+
+    #[test]
+    fn rejects_truncated_hunk() {
+        let err = parse_patch("--- a\n+++ b\n@@ -1,2 +1,2 @@\n x\n").unwrap_err();
+        assert!(err.to_string().contains("truncated"));
+    }
+
+    #[test]
+    fn applies_at_recorded_position() {
+        let hunks = &parse_patch(PATCH).unwrap()[0].hunks;
+        let out = apply_hunks(b"one\ntwo\nthree\nfour\nfive\nsix\nseven\n", hunks, 0);
+        assert_eq!(out.applied, 2);
+        assert!(out.rejected.is_empty());
+        assert_eq!(
+            out.content,
+            b"one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\n"
+        );
+    }
+
+    #[test]
+    fn applies_with_offset() {
+        let hunks = &parse_patch(PATCH).unwrap()[0].hunks;
+        let out = apply_hunks(
+            b"zero\nzero\none\ntwo\nthree\nfour\nfive\nsix\nseven\n",
+            hunks,
+            0,
+        );
+        assert_eq!(out.applied, 2);
+        assert!(out.content.ends_with(b"seven\neight\n"));
+        assert!(out.content.starts_with(b"zero\nzero\none\nTWO\nthree\n"));
+    }
+
+    #[test]
+    fn rejects_on_context_mismatch_without_fuzz() {
+        let hunks = &parse_patch(PATCH).unwrap()[0].hunks;
+        let original = b"uno\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let out = apply_hunks(original, hunks, 0);
+        assert_eq!(out.applied, 1);
+        assert_eq!(out.rejected, vec![Rejection { hunk: 1, line: 1 }]);
+        assert!(out.content.starts_with(b"uno\ntwo\n"));
+
+        let out = apply_hunks(original, hunks, 1);
+        assert_eq!(out.applied, 2);
+        assert!(out.content.starts_with(b"uno\nTWO\nthree\n"));
+    }
+
+    #[test]
+    fn handles_missing_newline_at_eof() {
+        let patch = "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-old\n\\ No newline at end of file\n+new\n";
+        let hunks = &parse_patch(patch).unwrap()[0].hunks;
+        assert_eq!(apply_hunks(b"old", hunks, 0).content, b"new\n");
+
+        let patch = "--- a/f\n+++ b/f\n@@ -1 +1 @@\n-old\n+new\n\\ No newline at end of file\n";
+        let hunks = &parse_patch(patch).unwrap()[0].hunks;
+        assert_eq!(apply_hunks(b"old\n", hunks, 0).content, b"new");
+    }
+
+    #[test]
+    fn preserves_crlf_lines() {
+        let patch = "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\r\n-b\r\n+c\r\n";
+        let hunks = &parse_patch(patch).unwrap()[0].hunks;
+        assert_eq!(apply_hunks(b"a\r\nb\r\n", hunks, 0).content, b"a\r\nc\r\n");
+    }
+}
@@ -0,0 +1,730 @@
+use crate::error::{Error, Result};
+use crate::events::OperationStats;
+use crate::model::{
+    LineEnding, LineRange, Occurrence, Operation, RegexEngine, ReplacementRange, ValidationMode,
+    Whitespace,
+};
+use crate::replacer::{
+    case_variants, BlockReplacer, Engine, Ensurer, LineAction, MapReplacer, Placement, Replacer,
+};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Backtracking steps the `fancy` engine may take per search when the manifest sets no limit.
+#[cfg(feature = "fancy")]
+const DEFAULT_BACKTRACK_LIMIT: usize = 1_000_000;
+
+/// The operations of a pipeline, compiled once per run and shared by every input.
+pub struct Plan {
+    pub steps: Vec<Step>,
+    /// Replacements made by each step so far in the run, for `${__global}`.
+    run_counts: Vec<AtomicUsize>,
+}
+
+/// One compiled operation.
+pub enum Step {
+    /// Match-based edits (`replace`, `delete`, `insert_before`, `insert_after`).
+    Replace {
+        replacer: PerEol<Replacer>,
+        name: &'static str,
+        /// Record match locations for the operation's `expect` / `expect_per_file`.
+        locate: bool,
+    },
+    /// Line-oriented edits (`delete_lines`, `keep_lines`, `replace_line`).
+    Lines {
+        replacer: PerEol<Replacer>,
+        action: LineAction,
+    },
+    Block(Box<PerEol<BlockReplacer>>),
+    /// Simultaneous literal replacements (`replace_map`, or `replace` with
+    /// `preserve_case`), counted per key.
+    Map {
+        replacer: PerEol<MapReplacer>,
+        name: &'static str,
+    },
+    Ensure(PerEol<Ensurer>),
+    NormalizeEol(LineEnding),
+}
+
+/// A compiled operation in both line ending conventions.
+/// Inserted text follows the line ending of the file it is applied to.
+pub struct PerEol<T> {
+    lf: T,
+    crlf: T,
+}
+
+impl<T: Clone> PerEol<T> {
+    fn new(compiled: T, with_eol: fn(T, LineEnding) -> T) -> Self {
+        Self {
+            lf: with_eol(compiled.clone(), LineEnding::Lf),
+            crlf: with_eol(compiled, LineEnding::Crlf),
+        }
+    }
+
+    pub fn get(&self, eol: LineEnding) -> &T {
+        match eol {
+            LineEnding::Lf => &self.lf,
+            LineEnding::Crlf => &self.crlf,
+        }
+    }
+}
+
+impl Step {
+    /// The per-input limit of the step (0 = unlimited).
+    fn limit(&self) -> usize {
+        match self {
+            Step::Replace { replacer, .. } | Step::Lines { replacer, .. } => {
+                replacer.get(LineEnding::Lf).limit()
+            }
+            Step::Map { replacer, .. } => replacer.get(LineEnding::Lf).limit(),
+            Step::Block(_) | Step::Ensure(_) | Step::NormalizeEol(_) => 0,
+        }
+    }
+
+    /// What step `index` did to one input: `count` toward the replacements, and whether
+    /// it `changed` the content.
+    pub fn stats(&self, index: usize, count: usize, changed: bool) -> OperationStats {
+        let limit = self.limit();
+        let warnings = (limit > 0 && count >= limit)
+            .then(|| format!("reached the limit of {} replacements", limit))
+            .into_iter()
+            .collect();
+        OperationStats {
+            index,
+            matches: count,
+            changed,
+            warnings,
+        }
+    }
+}
+
+impl PerEol<Replacer> {
+    /// The replacer for `eol`, limited to the ripgrep `matches` of the current file if any.
+    pub fn scoped(
+        &self,
+        eol: LineEnding,
+        matches: Option<&[ReplacementRange]>,
+    ) -> Cow<'_, Replacer> {
+        let replacer = self.get(eol);
+        match matches {
+            Some(ranges) => Cow::Owned(replacer.with_allowed_ranges(ranges)),
+            None => Cow::Borrowed(replacer),
+        }
+    }
+}
+
+impl PerEol<MapReplacer> {
+    /// The replacer for `eol`, limited to the ripgrep `matches` of the current file if any.
+    pub fn scoped(
+        &self,
+        eol: LineEnding,
+        matches: Option<&[ReplacementRange]>,
+    ) -> Cow<'_, MapReplacer> {
+        let replacer = self.get(eol);
+        match matches {
+            Some(ranges) => Cow::Owned(replacer.with_allowed_ranges(ranges)),
+            None => Cow::Borrowed(replacer),
+        }
+    }
+}
+
+impl Plan {
+    /// Compile every operation. Invalid patterns or replacements fail here,
+    /// before any input is read.
+    pub fn compile(operations: &[Operation]) -> Result<Self> {
+        let steps: Vec<Step> = operations.iter().map(compile_step).collect::<Result<_>>()?;
+        let run_counts = steps.iter().map(|_| AtomicUsize::new(0)).collect();
+        Ok(Self { steps, run_counts })
+    }
+
+    /// Whether a replacement numbers its matches across inputs with `${__global}`.
+    /// Inputs must then be processed one at a time, in order.
+    #[cfg(feature = "parallel")]
+    pub fn uses_global(&self) -> bool {
+        self.steps.iter().any(|step| match step {
+            Step::Replace { replacer, .. } => replacer.get(LineEnding::Lf).uses_global(),
+            _ => false,
+        })
+    }
+
+    /// Replacements made by step `index` in the inputs processed so far.
+    pub fn run_count(&self, index: usize) -> usize {
+        self.run_counts[index].load(Ordering::Relaxed)
+    }
+
+    /// Record `count` more replacements by step `index`.
+    pub fn add_run_count(&self, index: usize, count: usize) {
+        self.run_counts[index].fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+fn compile_step(op: &Operation) -> Result<Step> {
+    let step = match op {
+        Operation::Replace {
+            find,
+            with: replacement,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            multiline,
+            dot_matches_newline,
+            no_unicode,
+            limit,
+            range,
+            occurrence,
+            before,
+            after,
+            engine,
+            backtrack_limit,
+            whitespace,
+            expect,
+            expect_per_file,
+            expand,
+            validation_mode,
+            preserve_case,
+        } => {
+            check_occurrence(occurrence)?;
+            let engine = compile_engine(*engine, *backtrack_limit, *whitespace, *literal)?;
+            if *preserve_case {
+                if occurrence.is_some()
+                    || before.is_some()
+                    || after.is_some()
+                    || expect.is_some()
+                    || expect_per_file.is_some()
+                {
+                    return Err(Error::Validation(
+                        "occurrence, before, after and expect cannot be combined with preserve_case"
+                            .into(),
+                    ));
+                }
+                if engine != Engine::Regex || *whitespace != Whitespace::Exact {
+                    return Err(Error::Validation(
+                        "engine and whitespace cannot be combined with preserve_case".into(),
+                    ));
+                }
+                // FIND is literal and each spelling fixes its case, so only `word` applies
+                if *ignore_case || *smart_case || *multiline || *dot_matches_newline || *expand {
+                    return Err(Error::Validation(
+                        "ignore_case, smart_case, multiline, dot_matches_newline and expand \
+                         cannot be combined with preserve_case"
+                            .into(),
+                    ));
+                }
+                // One simultaneous pass over every spelling, so variants never re-match
+                let variants = case_variants(find, replacement)?;
+                let entries = variants.iter().map(|(find, with)| (find, with));
+                let replacer = MapReplacer::new(entries, false, *limit, range.clone())?
+                    .with_word(*word, !*no_unicode);
+                return Ok(Step::Map {
+                    replacer: PerEol::new(replacer, MapReplacer::with_eol),
+                    name: "replace",
+                });
+            }
+            let replacer = Replacer::new(
+                find,
+                replacement,
+                *literal,
+                *ignore_case,
+                *smart_case,
+                *word,
+                *multiline,
+                false, // single_line (not yet supported)
+                *dot_matches_newline,
+                *no_unicode,
+                *limit,
+                range.clone(),
+                None,
+                *expand,
+                *validation_mode,
+                engine,
+                *whitespace,
+            )
+            .and_then(|replacer| {
+                replacer.with_context(before.as_deref(), after.as_deref(), *literal)
+            })
+            .map_err(|e| Error::Validation(e.to_string()))?
+            .with_occurrence(*occurrence);
+            Step::Replace {
+                replacer: PerEol::new(replacer, Replacer::with_eol),
+                name: "replace",
+                locate: expect.is_some() || expect_per_file.is_some(),
+            }
+        }
+        Operation::Delete {
+            find,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            multiline,
+            dot_matches_newline,
+            no_unicode,
+            limit,
+            range,
+            occurrence,
+            engine,
+            backtrack_limit,
+            whitespace,
+            expect,
+            expect_per_file,
+        } => {
+            check_occurrence(occurrence)?;
+            let engine = compile_engine(*engine, *backtrack_limit, *whitespace, *literal)?;
+            let replacer = Replacer::new(
+                find,
+                "", // empty replacement
+                *literal,
+                *ignore_case,
+                *smart_case,
+                *word,
+                *multiline,
+                false, // single_line
+                *dot_matches_newline,
+                *no_unicode,
+                *limit,
+                range.clone(),
+                None,
+                false, // expand (no need for empty string)
+                ValidationMode::default(),
+                engine,
+                *whitespace,
+            )
+            .map_err(|e| Error::Validation(e.to_string()))?
+            .with_occurrence(*occurrence);
+            Step::Replace {
+                replacer: PerEol::new(replacer, Replacer::with_eol),
+                name: "delete",
+                locate: expect.is_some() || expect_per_file.is_some(),
+            }
+        }
+        Operation::InsertBefore {
+            find,
+            text,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            multiline,
+            dot_matches_newline,
+            no_unicode,
+            limit,
+            range,
+        }
+        | Operation::InsertAfter {
+            find,
+            text,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            multiline,
+            dot_matches_newline,
+            no_unicode,
+            limit,
+            range,
+        } => {
+            let placement = if matches!(op, Operation::InsertBefore { .. }) {
+                Placement::InsertBefore
+            } else {
+                Placement::InsertAfter
+            };
+
+            // Inserted text is taken verbatim, so no capture expansion
+            let replacer = Replacer::new(
+                find,
+                text,
+                *literal,
+                *ignore_case,
+                *smart_case,
+                *word,
+                *multiline,
+                false, // single_line
+                *dot_matches_newline,
+                *no_unicode,
+                *limit,
+                range.clone(),
+                None,
+                false, // expand
+                ValidationMode::default(),
+                Engine::Regex,
+                Whitespace::Exact,
+            )
+            .map_err(|e| Error::Validation(e.to_string()))?
+            .with_placement(placement);
+            Step::Replace {
+                replacer: PerEol::new(replacer, Replacer::with_eol),
+                name: "insert",
+                locate: false,
+            }
+        }
+        Operation::DeleteLines {
+            find,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            no_unicode,
+            limit,
+            range,
+        } => Step::Lines {
+            replacer: line_replacer(
+                find,
+                "",
+                *literal,
+                *ignore_case,
+                *smart_case,
+                *word,
+                *no_unicode,
+                *limit,
+                range,
+            )?,
+            action: LineAction::Delete,
+        },
+        Operation::KeepLines {
+            find,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            no_unicode,
+            range,
+        } => Step::Lines {
+            replacer: line_replacer(
+                find,
+                "",
+                *literal,
+                *ignore_case,
+                *smart_case,
+                *word,
+                *no_unicode,
+                0,
+                range,
+            )?,
+            action: LineAction::Keep,
+        },
+        Operation::ReplaceLine {
+            find,
+            with: replacement,
+            literal,
+            ignore_case,
+            smart_case,
+            word,
+            no_unicode,
+            limit,
+            range,
+        } => Step::Lines {
+            replacer: line_replacer(
+                find,
+                replacement,
+                *literal,
+                *ignore_case,
+                *smart_case,
+                *word,
+                *no_unicode,
+                *limit,
+                range,
+            )?,
+            action: LineAction::Replace,
+        },
+        Operation::ReplaceBlock {
+            start,
+            end,
+            with: replacement,
+            include_markers,
+            literal,
+            ignore_case,
+            smart_case,
+            multiline,
+            no_unicode,
+            limit,
+            range,
+        } => {
+            let marker = |pattern: &str| {
+                Replacer::new(
+                    pattern,
+                    "",
+                    *literal,
+                    *ignore_case,
+                    *smart_case,
+                    false, // word
+                    *multiline,
+                    false, // single_line
+                    false, // dot_matches_newline
+                    *no_unicode,
+                    0,
+                    None,
+                    None,
+                    false, // expand
+                    ValidationMode::default(),
+                    Engine::Regex,
+                    Whitespace::Exact,
+                )
+                .map_err(|e| Error::Validation(e.to_string()))
+            };
+            let replacer = BlockReplacer::new(
+                marker(start)?,
+                marker(end)?,
+                replacement,
+                *include_markers,
+                *limit,
+                range.clone(),
+            );
+            Step::Block(Box::new(PerEol::new(replacer, BlockReplacer::with_eol)))
+        }
+        Operation::EnsureLine {
+            line: content,
+            position,
+            anchor,
+            literal,
+            ignore_case,
+        }
+        | Operation::EnsureBlock {
+            block: content,
+            position,
+            anchor,
+            literal,
+            ignore_case,
+        } => {
+            let anchor = anchor
+                .as_deref()
+                .map(|pattern| {
+                    Replacer::new(
+                        pattern,
+                        "",
+                        *literal,
+                        *ignore_case,
+                        false, // smart_case
+                        false, // word
+                        false, // multiline
+                        false, // single_line
+                        false, // dot_matches_newline
+                        false, // no_unicode
+                        0,
+                        None,
+                        None,
+                        false, // expand
+                        ValidationMode::default(),
+                        Engine::Regex,
+                        Whitespace::Exact,
+                    )
+                    .map_err(|e| Error::Validation(e.to_string()))
+                })
+                .transpose()?;
+            let whole_line = matches!(op, Operation::EnsureLine { .. });
+            let ensurer = Ensurer::new(content, whole_line, *position, anchor)?;
+            Step::Ensure(PerEol::new(ensurer, Ensurer::with_eol))
+        }
+        Operation::ReplaceMap {
+            map,
+            ignore_case,
+            limit,
+            range,
+        } => {
+            let replacer = MapReplacer::new(map, *ignore_case, *limit, range.clone())?;
+            Step::Map {
+                replacer: PerEol::new(replacer, MapReplacer::with_eol),
+                name: "replace_map",
+            }
+        }
+        Operation::NormalizeEol { eol } => Step::NormalizeEol(*eol),
+    };
+    Ok(step)
+}
+
+/// Reject an occurrence selector that cannot select anything.
+fn check_occurrence(occurrence: &Option<Occurrence>) -> Result<()> {
+    if let Some(occurrence) = occurrence {
+        if occurrence.start == 0 || occurrence.end == Some(0) {
+            return Err(Error::Validation(
+                "occurrence numbers start at 1 (or -1 for the last match)".into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Resolve the manifest's engine choice to the engine a pattern is compiled with.
+fn compile_engine(
+    engine: RegexEngine,
+    backtrack_limit: Option<usize>,
+    whitespace: Whitespace,
+    literal: bool,
+) -> Result<Engine> {
+    // As on the command line, where `--whitespace` conflicts with `--regex`
+    if whitespace == Whitespace::Flexible && !literal {
+        return Err(Error::Validation(
+            "flexible whitespace requires `literal`, as `find` is matched literally".into(),
+        ));
+    }
+    match engine {
+        // A flexible `find` is a literal, so only the default engine is needed
+        RegexEngine::Fancy if whitespace == Whitespace::Flexible => Err(Error::Validation(
+            "engine `fancy` cannot be combined with flexible whitespace".into(),
+        )),
+        RegexEngine::Regex if backtrack_limit.is_some() => Err(Error::Validation(
+            "backtrack_limit only applies to engine `fancy`".into(),
+        )),
+        RegexEngine::Regex => Ok(Engine::Regex),
+        #[cfg(feature = "fancy")]
+        RegexEngine::Fancy => Ok(Engine::Fancy {
+            backtrack_limit: backtrack_limit.unwrap_or(DEFAULT_BACKTRACK_LIMIT),
+        }),
+        #[cfg(not(feature = "fancy"))]
+        RegexEngine::Fancy => Err(Error::FeatureNotEnabled("fancy")),
+    }
+}
+
+/// Build a replacer for the line-oriented operations.
+/// Patterns are matched against a single line, so multi-line flags never apply.
+#[allow(clippy::too_many_arguments)]
+fn line_replacer(
+    find: &str,
+    replacement: &str,
+    literal: bool,
+    ignore_case: bool,
+    smart_case: bool,
+    word: bool,
+    no_unicode: bool,
+    limit: usize,
+    range: &Option<LineRange>,
+) -> Result<PerEol<Replacer>> {
+    let replacer = Replacer::new(
+        find,
+        replacement,
+        literal,
+        ignore_case,
+        smart_case,
+        word,
+        false, // multiline
+        false, // single_line
+        false, // dot_matches_newline
+        no_unicode,
+        limit,
+        range.clone(),
+        None,
+        false, // expand
+        ValidationMode::default(),
+        Engine::Regex,
+        Whitespace::Exact,
+    )
+    .map_err(|e| Error::Validation(e.to_string()))?;
+    Ok(PerEol::new(replacer, Replacer::with_eol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replacer::TemplateVars;
+
+    fn replace(find: &str, with: &str) -> Operation {
+        Operation::Replace {
+            find: find.into(),
+            with: with.into(),
+            literal: false,
+            ignore_case: false,
+            smart_case: false,
+            word: false,
+            multiline: false,
+            dot_matches_newline: false,
+            no_unicode: false,
+            limit: 0,
+            range: None,
+            occurrence: None,
+            before: None,
+            after: None,
+            engine: RegexEngine::default(),
+            backtrack_limit: None,
+            whitespace: Whitespace::default(),
+            expect: None,
+            expect_per_file: None,
+            expand: false,
+            validation_mode: ValidationMode::default(),
+            preserve_case: false,
+        }
+    }
+
+    #[test]
+    fn plan_is_shareable_across_threads() {
+        fn assert_sync<T: Sync + Send>() {}
+        assert_sync::<Plan>();
+    }
+
+    #[test]
+    fn compile_fails_on_any_invalid_operation() {
+        let err = Plan::compile(&[replace("ok", "x"), replace("(", "x")])
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), "E_VALIDATION");
+    }
+
+    #[test]
+    fn engine_options_are_checked() {
+        let mut op = replace("a", "b");
+        if let Operation::Replace {
+            backtrack_limit, ..
+        } = &mut op
+        {
+            *backtrack_limit = Some(10);
+        }
+        let err = Plan::compile(&[op.clone()]).err().unwrap();
+        assert!(err.to_string().contains("only applies to engine `fancy`"));
+
+        if let Operation::Replace { engine, .. } = &mut op {
+            *engine = RegexEngine::Fancy;
+        }
+        let compiled = Plan::compile(&[op]);
+        if cfg!(feature = "fancy") {
+            assert!(compiled.is_ok());
+        } else {
+            assert_eq!(compiled.err().unwrap().code(), "E_FEATURE_DISABLED");
+        }
+    }
+
+    #[test]
+    fn flexible_whitespace_requires_literal() {
+        let mut op = replace("a  b", "c");
+        if let Operation::Replace { whitespace, .. } = &mut op {
+            *whitespace = Whitespace::Flexible;
+        }
+        let err = Plan::compile(&[op.clone()]).err().unwrap();
+        assert!(err.to_string().contains("requires `literal`"));
+
+        if let Operation::Replace { literal, .. } = &mut op {
+            *literal = true;
+        }
+        assert!(Plan::compile(&[op]).is_ok());
+    }
+
+    #[test]
+    fn replacement_text_follows_line_ending() {
+        let plan = Plan::compile(&[replace("b", "x\ny")]).unwrap();
+        let Step::Replace { replacer, .. } = &plan.steps[0] else {
+            panic!("expected a replace step");
+        };
+        let (out, _) = replacer
+            .get(LineEnding::Lf)
+            .replace_with_count(b"abc", &TemplateVars::default())
+            .unwrap();
+        assert_eq!(&out[..], b"ax\nyc");
+        let (out, _) = replacer
+            .get(LineEnding::Crlf)
+            .replace_with_count(b"abc", &TemplateVars::default())
+            .unwrap();
+        assert_eq!(&out[..], b"ax\r\nyc");
+    }
+
+    #[test]
+    fn scoped_replacer_only_touches_matches() {
+        let plan = Plan::compile(&[replace("x", "y")]).unwrap();
+        let Step::Replace { replacer, .. } = &plan.steps[0] else {
+            panic!("expected a replace step");
+        };
+        let ranges = [ReplacementRange { start: 2, end: 3 }];
+        let (out, n) = replacer
+            .scoped(LineEnding::Lf, Some(&ranges))
+            .replace_with_count(b"x x x", &TemplateVars::default())
+            .unwrap();
+        assert_eq!((&out[..], n), (&b"x y x"[..], 1));
+    }
+}
@@ -1,5 +1,8 @@
-use crate::model::Pipeline;
-use crate::reporter::Report;
+use crate::model::{Operation, Pipeline};
+use crate::reporter::{FileResult, Report};
+
+/// Match locations listed per violated expectation; the rest are only counted.
+const MAX_LISTED_LOCATIONS: usize = 10;
 
 /// Enforce policies that affect the pipeline configuration before execution.
 /// E.g., validate_only forces dry_run.
@@ -67,6 +70,64 @@ impl<'a> PolicyEnforcer<'a> {
                 report.modified
             ));
         }
+        if report.policy_violation.is_none() {
+            report.policy_violation = self.check_expected_matches(report);
+        }
+    }
+
+    /// Check each operation's `expect` / `expect_per_file` against the matches it found.
+    /// Violations name the operation index and the candidate match locations.
+    fn check_expected_matches(&self, report: &Report) -> Option<String> {
+        let mut violations = Vec::new();
+        for (index, op) in self.pipeline.operations.iter().enumerate() {
+            let (expect, expect_per_file) = match op {
+                Operation::Replace {
+                    expect,
+                    expect_per_file,
+                    ..
+                }
+                | Operation::Delete {
+                    expect,
+                    expect_per_file,
+                    ..
+                } => (*expect, *expect_per_file),
+                _ => continue,
+            };
+            // Files that failed or were skipped have no locations and are not counted
+            let located: Vec<(&FileResult, &Vec<(usize, usize)>)> = report
+                .files
+                .iter()
+                .filter_map(|file| file.expected_matches.get(&index).map(|l| (file, l)))
+                .collect();
+
+            if let Some(expected) = expect_per_file {
+                for &(file, locations) in &located {
+                    if locations.len() != expected {
+                        violations.push(format!(
+                            "operation {} expected {} matches per file, found {} in {}{}",
+                            index,
+                            expected,
+                            locations.len(),
+                            file.path.display(),
+                            list_locations(&[(file, locations)])
+                        ));
+                    }
+                }
+            }
+            if let Some(expected) = expect {
+                let found: usize = located.iter().map(|(_, locations)| locations.len()).sum();
+                if found != expected {
+                    violations.push(format!(
+                        "operation {} expected {} matches, found {}{}",
+                        index,
+                        expected,
+                        found,
+                        list_locations(&located)
+                    ));
+                }
+            }
+        }
+        (!violations.is_empty()).then(|| violations.join("; "))
     }
 
     /// Check if the transaction manager should commit.
@@ -86,6 +147,29 @@ impl<'a> PolicyEnforcer<'a> {
     }
 }
 
+/// `: path:line:col, ...` for the first few matches, or nothing without matches.
+fn list_locations(located: &[(&FileResult, &Vec<(usize, usize)>)]) -> String {
+    let all: Vec<String> = located
+        .iter()
+        .flat_map(|(file, locations)| {
+            locations
+                .iter()
+                .map(|(line, col)| format!("{}:{}:{}", file.path.display(), line, col))
+        })
+        .collect();
+    if all.is_empty() {
+        return String::new();
+    }
+    let mut listed = format!(
+        ": {}",
+        all[..all.len().min(MAX_LISTED_LOCATIONS)].join(", ")
+    );
+    if all.len() > MAX_LISTED_LOCATIONS {
+        listed.push_str(&format!(" and {} more", all.len() - MAX_LISTED_LOCATIONS));
+    }
+    listed
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,6 +268,65 @@ mod tests {
             .contains("Changes detected"));
     }
 
+    #[test]
+    fn enforce_post_run_expected_matches_per_operation() {
+        let mut p = default_pipeline();
+        p.operations = serde_json::from_str(
+            r#"[
+                {"type": "replace", "find": "a", "with": "b"},
+                {"type": "delete", "find": "c", "expect_per_file": 1},
+                {"type": "replace", "find": "d", "with": "e", "expect": 2}
+            ]"#,
+        )
+        .unwrap();
+        let enforcer = PolicyEnforcer::new(&p);
+        let file = |path: &str, c: Vec<(usize, usize)>, d: Vec<(usize, usize)>| FileResult {
+            path: path.into(),
+            expected_matches: [(1, c), (2, d)].into(),
+            ..Default::default()
+        };
+
+        let mut report = Report::new(false, false);
+        report.add_result(file("a.txt", vec![(1, 1)], vec![(2, 3)]));
+        report.add_result(file("b.txt", vec![(4, 1)], vec![(1, 1)]));
+        enforcer.enforce_post_run(&mut report);
+        assert!(report.policy_violation.is_none());
+
+        let mut report = Report::new(false, false);
+        report.add_result(file("a.txt", vec![(1, 1), (3, 5)], vec![(2, 3)]));
+        report.add_result(file("b.txt", vec![(4, 1)], vec![]));
+        enforcer.enforce_post_run(&mut report);
+        assert_eq!(
+            report.policy_violation.unwrap(),
+            "operation 1 expected 1 matches per file, found 2 in a.txt: a.txt:1:1, a.txt:3:5; \
+             operation 2 expected 2 matches, found 1: a.txt:2:3"
+        );
+    }
+
+    #[test]
+    fn enforce_post_run_expected_matches_with_global_expect() {
+        let mut p = default_pipeline();
+        p.expect = Some(2);
+        p.operations = serde_json::from_str(
+            r#"[{"type": "replace", "find": "a", "with": "b", "expect_per_file": 1}]"#,
+        )
+        .unwrap();
+        let enforcer = PolicyEnforcer::new(&p);
+
+        let mut report = Report::new(false, false);
+        report.add_result(FileResult {
+            path: "a.txt".into(),
+            replacements: 2,
+            expected_matches: [(0, vec![(1, 1), (2, 1)])].into(),
+            ..Default::default()
+        });
+        enforcer.enforce_post_run(&mut report);
+        assert_eq!(
+            report.policy_violation.unwrap(),
+            "operation 0 expected 1 matches per file, found 2 in a.txt: a.txt:1:1, a.txt:2:1"
+        );
+    }
+
     #[test]
     fn should_commit_returns_false_if_validate_only() {
         let mut p = default_pipeline();
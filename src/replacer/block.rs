@@ -0,0 +1,212 @@
+use super::{build_line_offsets, is_in_range, Replacer};
+use crate::eol;
+use crate::error::{Error, Result};
+use crate::model::{LineEnding, LineRange};
+use std::borrow::Cow;
+
+/// Replaces the text between pairs of start/end markers.
+///
+/// Every marker in the text must be part of exactly one well-formed block:
+/// an end marker without a start, a start marker without an end, or a start
+/// marker inside an open block all fail with `Error::BlockMarkers`.
+#[derive(Clone)]
+pub struct BlockReplacer {
+    start: Replacer,
+    end: Replacer,
+    replacement: Vec<u8>,
+    include_markers: bool,
+    max_replacements: usize,
+    range: Option<LineRange>,
+}
+
+impl BlockReplacer {
+    /// Create a block replacer from two marker matchers.
+    /// Only the matchers of `start` and `end` are used; their replacement text is ignored.
+    pub fn new(
+        start: Replacer,
+        end: Replacer,
+        replacement: &str,
+        include_markers: bool,
+        max_replacements: usize,
+        range: Option<LineRange>,
+    ) -> Self {
+        Self {
+            start,
+            end,
+            replacement: replacement.as_bytes().to_vec(),
+            include_markers,
+            max_replacements,
+            range,
+        }
+    }
+
+    /// Rewrite the newlines of the replacement text to `eol`.
+    pub fn with_eol(mut self, eol: LineEnding) -> Self {
+        if let (Cow::Owned(bytes), _) = eol::normalize(&self.replacement, eol) {
+            self.replacement = bytes;
+        }
+        self
+    }
+
+    /// Replace every block and return the new text along with the number of blocks replaced.
+    pub fn replace_with_count<'a>(&self, text: &'a [u8]) -> Result<(Cow<'a, [u8]>, usize)> {
+        let starts = self.start.spans(text).collect::<Result<Vec<_>>>()?;
+        let ends = self.end.spans(text).collect::<Result<Vec<_>>>()?;
+        let line_offsets = build_line_offsets(text);
+        let line_of = |offset: usize| match line_offsets.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+
+        // Pair markers up. When a start and an end marker are both candidates at
+        // the same offset (e.g. identical patterns), an open block prefers the end.
+        let mut blocks = Vec::new();
+        let mut open: Option<(usize, usize)> = None;
+        let mut cursor = 0;
+        let (mut si, mut ei) = (0, 0);
+        loop {
+            while si < starts.len() && starts[si].0 < cursor {
+                si += 1;
+            }
+            while ei < ends.len() && ends[ei].0 < cursor {
+                ei += 1;
+            }
+            let next_start = starts.get(si).copied();
+            let next_end = ends.get(ei).copied();
+
+            match open {
+                None => match (next_start, next_end) {
+                    (Some(s), e) if e.is_none_or(|e| s.0 <= e.0) => {
+                        open = Some(s);
+                        cursor = s.1.max(s.0 + 1);
+                    }
+                    (_, Some(e)) => {
+                        return Err(Error::BlockMarkers(format!(
+                            "end marker on line {} has no matching start marker",
+                            line_of(e.0)
+                        )));
+                    }
+                    _ => break,
+                },
+                Some(s) => match (next_start, next_end) {
+                    (n, Some(e)) if n.is_none_or(|n| e.0 <= n.0) => {
+                        blocks.push((s, e));
+                        open = None;
+                        cursor = e.1.max(e.0 + 1);
+                    }
+                    (Some(n), _) => {
+                        return Err(Error::BlockMarkers(format!(
+                            "start marker on line {} is nested inside the block opened on line {}",
+                            line_of(n.0),
+                            line_of(s.0)
+                        )));
+                    }
+                    _ => {
+                        return Err(Error::BlockMarkers(format!(
+                            "start marker on line {} has no matching end marker",
+                            line_of(s.0)
+                        )));
+                    }
+                },
+            }
+        }
+
+        let mut new_data = Vec::with_capacity(text.len());
+        let mut last_end = 0;
+        let mut count = 0;
+        for (start, end) in blocks {
+            if self.max_replacements > 0 && count >= self.max_replacements {
+                break;
+            }
+            if let Some(range) = &self.range {
+                if !is_in_range(start.0, range, &line_offsets) {
+                    continue;
+                }
+            }
+
+            let (from, to) = if self.include_markers {
+                (start.0, end.1)
+            } else {
+                (start.1, end.0)
+            };
+            new_data.extend_from_slice(&text[last_end..from]);
+            new_data.extend_from_slice(&self.replacement);
+            last_end = to;
+            count += 1;
+        }
+
+        if count == 0 {
+            return Ok((Cow::Borrowed(text), 0));
+        }
+        new_data.extend_from_slice(&text[last_end..]);
+        Ok((Cow::Owned(new_data), count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ValidationMode, Whitespace};
+    use crate::replacer::Engine;
+
+    fn marker(pattern: &str) -> Replacer {
+        Replacer::new(
+            pattern,
+            "",
+            true,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            0,
+            None,
+            None,
+            false,
+            ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
+        )
+        .unwrap()
+    }
+
+    fn block(include_markers: bool) -> BlockReplacer {
+        BlockReplacer::new(marker("<<"), marker(">>"), "new", include_markers, 0, None)
+    }
+
+    #[test]
+    fn replaces_between_markers() {
+        let (out, count) = block(false).replace_with_count(b"a<<x>>b<<y>>c").unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(&out[..], b"a<<new>>b<<new>>c");
+
+        let (out, count) = block(true).replace_with_count(b"a<<x>>b").unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(&out[..], b"anewb");
+    }
+
+    #[test]
+    fn no_markers_is_not_an_error() {
+        let (out, count) = block(false).replace_with_count(b"plain").unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(&out[..], b"plain");
+    }
+
+    #[test]
+    fn identical_markers_pair_up() {
+        let fence = BlockReplacer::new(marker("---"), marker("---"), "\n", false, 0, None);
+        let (out, count) = fence.replace_with_count(b"---\nold\n---\n").unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(&out[..], b"---\n---\n");
+    }
+
+    #[test]
+    fn malformed_markers_fail() {
+        for input in [&b"a<<x"[..], b"x>>", b"<<a<<b>>>>"] {
+            let err = block(false).replace_with_count(input).unwrap_err();
+            assert_eq!(err.code(), "E_BLOCK_MARKERS", "input {:?}", input);
+        }
+    }
+}
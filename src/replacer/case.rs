@@ -0,0 +1,149 @@
+use crate::error::{Error, Result};
+
+/// Naming conventions recognized by `preserve_case`, in order of precedence
+/// when two of them spell FIND the same way (e.g. a single lowercase word).
+const CONVENTIONS: [Convention; 5] = [
+    Convention::Snake,
+    Convention::Screaming,
+    Convention::Kebab,
+    Convention::Camel,
+    Convention::Pascal,
+];
+
+/// A naming convention for multi-word identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convention {
+    /// `user_id`
+    Snake,
+    /// `USER_ID`
+    Screaming,
+    /// `user-id`
+    Kebab,
+    /// `userId`
+    Camel,
+    /// `UserId`
+    Pascal,
+}
+
+impl Convention {
+    /// Respell an identifier, written in any convention, in this one.
+    pub fn convert(self, text: &str) -> String {
+        self.join(&split_words(text))
+    }
+
+    fn join(self, words: &[String]) -> String {
+        match self {
+            Convention::Snake => words.join("_"),
+            Convention::Screaming => words.join("_").to_uppercase(),
+            Convention::Kebab => words.join("-"),
+            Convention::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| if i == 0 { w.clone() } else { capitalize(w) })
+                .collect(),
+            Convention::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+        }
+    }
+}
+
+/// Every spelling of `find` in the supported conventions, each paired with
+/// `replace` spelled the same way. Duplicate spellings are dropped.
+pub fn case_variants(find: &str, replace: &str) -> Result<Vec<(String, String)>> {
+    let find_words = split_words(find);
+    if find_words.is_empty() {
+        return Err(Error::Validation(format!(
+            "preserve_case needs FIND to contain letters or digits, got {:?}",
+            find
+        )));
+    }
+    let replace_words = split_words(replace);
+
+    let mut variants: Vec<(String, String)> = Vec::new();
+    for convention in CONVENTIONS {
+        let spelled = convention.join(&find_words);
+        if variants.iter().all(|(existing, _)| *existing != spelled) {
+            variants.push((spelled, convention.join(&replace_words)));
+        }
+    }
+    Ok(variants)
+}
+
+/// Split an identifier into lowercase words at `_`, `-`, whitespace and case changes.
+/// An uppercase run followed by a lowercase letter ends before its last capital,
+/// so `HTTPServer` is `http` + `server`.
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in text.split(|c: char| c == '_' || c == '-' || c.is_whitespace()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            let prev = i.checked_sub(1).map(|p| chars[p]);
+            let next = chars.get(i + 1);
+            let boundary = c.is_uppercase()
+                && prev.is_some_and(|p| {
+                    p.is_lowercase()
+                        || p.is_ascii_digit()
+                        || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase()))
+                });
+            if boundary && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.extend(c.to_lowercase());
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Uppercase the first character of `word`.
+pub fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_every_convention() {
+        for text in [
+            "user_id", "USER_ID", "user-id", "userId", "UserId", "user id",
+        ] {
+            assert_eq!(split_words(text), ["user", "id"], "{}", text);
+        }
+        assert_eq!(split_words("HTTPServer2Go"), ["http", "server2", "go"]);
+    }
+
+    #[test]
+    fn variants_follow_find_convention() {
+        let variants = case_variants("user_id", "account_id").unwrap();
+        let pairs: Vec<(&str, &str)> = variants
+            .iter()
+            .map(|(f, r)| (f.as_str(), r.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("user_id", "account_id"),
+                ("USER_ID", "ACCOUNT_ID"),
+                ("user-id", "account-id"),
+                ("userId", "accountId"),
+                ("UserId", "AccountId"),
+            ]
+        );
+    }
+
+    #[test]
+    fn single_words_collapse_to_three_spellings() {
+        let variants = case_variants("user", "account").unwrap();
+        let finds: Vec<&str> = variants.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(finds, ["user", "USER", "User"]);
+        assert!(case_variants("__", "x").is_err());
+    }
+}
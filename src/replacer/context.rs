@@ -0,0 +1,103 @@
+use crate::error::{Error, Result};
+use regex::bytes::RegexBuilder;
+use regex_automata::hybrid::dfa::DFA;
+use regex_automata::nfa::thompson;
+use regex_automata::util::syntax;
+use regex_automata::{meta, Anchored, Input, MatchKind};
+
+/// Text that must surround a match without being part of it.
+///
+/// Both patterns are matched in the whole text rather than in the text on either side,
+/// so assertions like `\b` or `$` next to the match see the real neighbouring text.
+#[derive(Clone)]
+pub struct Context {
+    before: Option<Before>,
+    after: Option<meta::Regex>,
+}
+
+/// A pattern that must end where the match starts.
+#[derive(Clone)]
+struct Before {
+    /// Searched backwards from the match start, so only the context itself is scanned.
+    reverse: DFA,
+    /// Searched forwards when `reverse` gives up, e.g. on a Unicode `\b` next to
+    /// non-ASCII text. Reports the last end of any match.
+    forward: meta::Regex,
+}
+
+impl Context {
+    /// Compile `before` and `after` with the same flags as the pattern they surround.
+    pub fn new(before: Option<&str>, after: Option<&str>, syntax: syntax::Config) -> Result<Self> {
+        let before = before
+            .map(|pattern| -> Result<Before> {
+                validate(pattern, syntax)?;
+                let reverse = DFA::builder()
+                    .syntax(syntax)
+                    .thompson(thompson::Config::new().reverse(true))
+                    .configure(
+                        DFA::config()
+                            .match_kind(MatchKind::All)
+                            .unicode_word_boundary(true),
+                    )
+                    .build(pattern)
+                    .map_err(invalid)?;
+                let forward = meta::Regex::builder()
+                    .syntax(syntax)
+                    .configure(meta::Regex::config().match_kind(MatchKind::All))
+                    .build(pattern)
+                    .map_err(invalid)?;
+                Ok(Before { reverse, forward })
+            })
+            .transpose()?;
+        let after = after
+            .map(|pattern| {
+                validate(pattern, syntax)?;
+                meta::Regex::builder()
+                    .syntax(syntax)
+                    .build(pattern)
+                    .map_err(invalid)
+            })
+            .transpose()?;
+        Ok(Self { before, after })
+    }
+
+    /// Whether `text[start..end]` is preceded by `before` and followed by `after`.
+    pub fn surrounds(&self, text: &[u8], start: usize, end: usize) -> bool {
+        self.before
+            .as_ref()
+            .is_none_or(|before| before.ends_at(text, start))
+            && self.after.as_ref().is_none_or(|after| {
+                after.is_match(Input::new(text).range(end..).anchored(Anchored::Yes))
+            })
+    }
+}
+
+impl Before {
+    fn ends_at(&self, text: &[u8], at: usize) -> bool {
+        let mut cache = self.reverse.create_cache();
+        let input = Input::new(text).range(..at).anchored(Anchored::Yes);
+        match self.reverse.try_search_rev(&mut cache, &input) {
+            Ok(found) => found.is_some(),
+            Err(_) => self
+                .forward
+                .search_half(&Input::new(text).range(..at))
+                .is_some_and(|found| found.offset() == at),
+        }
+    }
+}
+
+/// Report an invalid pattern the same way as an invalid `find`.
+fn validate(pattern: &str, syntax: syntax::Config) -> Result<()> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(syntax.get_case_insensitive())
+        .unicode(syntax.get_unicode())
+        .multi_line(syntax.get_multi_line())
+        .dot_matches_new_line(syntax.get_dot_matches_new_line())
+        .build()
+        .map(drop)
+        .map_err(Error::Regex)
+}
+
+fn invalid(e: impl std::fmt::Display) -> Error {
+    Error::Validation(format!("Invalid regex: {}", e))
+}
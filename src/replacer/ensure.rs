@@ -0,0 +1,202 @@
+use super::Replacer;
+use crate::eol;
+use crate::error::{Error, Result};
+use crate::model::{EnsurePosition, LineEnding};
+use memchr::memmem;
+
+/// Adds a line or block of text at an anchor unless it is already present.
+///
+/// Applying an `Ensurer` to its own output is always a no-op, which makes
+/// manifests built from `ensure_line` / `ensure_block` safe to re-run.
+#[derive(Clone)]
+pub struct Ensurer {
+    content: Vec<u8>,
+    whole_line: bool,
+    position: EnsurePosition,
+    anchor: Option<Replacer>,
+    eol: LineEnding,
+}
+
+impl Ensurer {
+    /// Create an ensurer for `content`.
+    ///
+    /// With `whole_line`, content counts as present only if some line equals it exactly
+    /// (ignoring the line ending); otherwise it counts as present if it occurs anywhere.
+    /// A single trailing line ending on `content` is ignored.
+    pub fn new(
+        content: &str,
+        whole_line: bool,
+        position: EnsurePosition,
+        anchor: Option<Replacer>,
+    ) -> Result<Self> {
+        let content = content
+            .strip_suffix('\n')
+            .map(|c| c.strip_suffix('\r').unwrap_or(c))
+            .unwrap_or(content);
+
+        if whole_line && content.contains('\n') {
+            return Err(Error::Validation(
+                "ensure_line content must be a single line (use ensure_block)".into(),
+            ));
+        }
+        if content.is_empty() {
+            return Err(Error::Validation("ensure content must not be empty".into()));
+        }
+        let needs_anchor = matches!(position, EnsurePosition::Before | EnsurePosition::After);
+        if needs_anchor && anchor.is_none() {
+            return Err(Error::Validation(format!(
+                "ensure position '{:?}' requires an anchor pattern",
+                position
+            )));
+        }
+
+        Ok(Self {
+            content: content.as_bytes().to_vec(),
+            whole_line,
+            position,
+            anchor,
+            eol: LineEnding::Lf,
+        })
+    }
+
+    /// Set the line ending used inside the content and to separate it from its neighbours.
+    pub fn with_eol(mut self, eol: LineEnding) -> Self {
+        if let (std::borrow::Cow::Owned(bytes), _) = eol::normalize(&self.content, eol) {
+            self.content = bytes;
+        }
+        self.eol = eol;
+        self
+    }
+
+    /// Check whether the content is already present in `text`.
+    pub fn is_present(&self, text: &[u8]) -> bool {
+        if !self.whole_line {
+            return memmem::find(text, &self.content).is_some();
+        }
+        text.split(|&b| b == b'\n').any(|line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            line == self.content.as_slice()
+        })
+    }
+
+    /// Add the content to `text`, or return `None` if it is already present.
+    pub fn apply(&self, text: &[u8]) -> Result<Option<Vec<u8>>> {
+        if self.is_present(text) {
+            return Ok(None);
+        }
+
+        let offset = match self.position {
+            EnsurePosition::Start => 0,
+            EnsurePosition::End => text.len(),
+            EnsurePosition::Before | EnsurePosition::After => {
+                let anchor = self.anchor.as_ref().expect("validated in Ensurer::new");
+                let (start, end) =
+                    anchor.spans(text).next().transpose()?.ok_or_else(|| {
+                        Error::Validation("ensure anchor pattern not found".into())
+                    })?;
+                if self.position == EnsurePosition::Before {
+                    text[..start]
+                        .iter()
+                        .rposition(|&b| b == b'\n')
+                        .map_or(0, |i| i + 1)
+                } else {
+                    let last = if end > start { end - 1 } else { start };
+                    memchr::memchr(b'\n', &text[last..]).map_or(text.len(), |i| last + i + 1)
+                }
+            }
+        };
+
+        let eol = self.eol.as_bytes();
+        let mut new_data = Vec::with_capacity(text.len() + self.content.len() + 4);
+        new_data.extend_from_slice(&text[..offset]);
+        if offset > 0 && text[offset - 1] != b'\n' {
+            new_data.extend_from_slice(eol);
+        }
+        new_data.extend_from_slice(&self.content);
+        new_data.extend_from_slice(eol);
+        new_data.extend_from_slice(&text[offset..]);
+        Ok(Some(new_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ValidationMode, Whitespace};
+    use crate::replacer::Engine;
+
+    fn anchor(pattern: &str) -> Option<Replacer> {
+        Some(
+            Replacer::new(
+                pattern,
+                "",
+                true,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                0,
+                None,
+                None,
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Exact,
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn ensure_line_is_idempotent() {
+        let ensurer =
+            Ensurer::new("use c;", true, EnsurePosition::After, anchor("use b;")).unwrap();
+        let once = ensurer
+            .apply(b"use a;\nuse b;\nfn main() {}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(once, b"use a;\nuse b;\nuse c;\nfn main() {}");
+        assert!(ensurer.apply(&once).unwrap().is_none());
+    }
+
+    #[test]
+    fn ensure_line_requires_whole_line() {
+        let ensurer = Ensurer::new("b", true, EnsurePosition::End, None).unwrap();
+        assert_eq!(ensurer.apply(b"abc").unwrap().unwrap(), b"abc\nb\n");
+        assert!(ensurer.apply(b"a\r\nb\r\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn ensure_block_positions() {
+        let ensurer = Ensurer::new("x\ny\n", false, EnsurePosition::Start, None).unwrap();
+        assert_eq!(ensurer.apply(b"body\n").unwrap().unwrap(), b"x\ny\nbody\n");
+        assert!(ensurer.apply(b"x\ny").unwrap().is_none());
+
+        let ensurer =
+            Ensurer::new("x\ny", false, EnsurePosition::Before, anchor("fn main")).unwrap();
+        assert_eq!(
+            ensurer.apply(b"a\nfn main() {}\n").unwrap().unwrap(),
+            b"a\nx\ny\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn ensure_uses_line_ending() {
+        let ensurer = Ensurer::new("b", true, EnsurePosition::End, None)
+            .unwrap()
+            .with_eol(LineEnding::Crlf);
+        assert_eq!(ensurer.apply(b"a").unwrap().unwrap(), b"a\r\nb\r\n");
+    }
+
+    #[test]
+    fn ensure_errors() {
+        assert!(Ensurer::new("a\nb", true, EnsurePosition::End, None).is_err());
+        assert!(Ensurer::new("a", true, EnsurePosition::After, None).is_err());
+
+        let ensurer = Ensurer::new("a", true, EnsurePosition::After, anchor("zzz")).unwrap();
+        assert!(ensurer.apply(b"b\n").is_err());
+    }
+}
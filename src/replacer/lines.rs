@@ -0,0 +1,128 @@
+use super::{check_allowed_range_optimized, Matcher, Replacer};
+use memchr::memmem;
+use std::borrow::Cow;
+
+/// Split a line into its content and its terminator (`\n`, `\r\n` or nothing).
+pub fn split_terminator(line: &[u8]) -> (&[u8], &[u8]) {
+    let content_len = match line {
+        [.., b'\r', b'\n'] => line.len() - 2,
+        [.., b'\n'] => line.len() - 1,
+        _ => line.len(),
+    };
+    line.split_at(content_len)
+}
+
+/// What a line-oriented operation does with each line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineAction {
+    /// Drop lines that match.
+    Delete,
+    /// Drop lines that do not match.
+    Keep,
+    /// Substitute the content of matching lines, keeping their terminator.
+    Replace,
+}
+
+impl Replacer {
+    /// Apply a line-oriented action and return the new text along with the number of lines affected.
+    ///
+    /// The pattern is matched against each line without its terminator (`\n` or `\r\n`),
+    /// so terminators are preserved exactly for every line that is kept.
+    /// Lines outside the configured `LineRange` are always kept untouched.
+    pub fn apply_lines<'a>(&self, text: &'a [u8], action: LineAction) -> (Cow<'a, [u8]>, usize) {
+        let mut new_data = Vec::with_capacity(text.len());
+        let mut count = 0;
+        let mut allowed_cursor = 0;
+        let mut line_start = 0;
+        let mut line_number = 0;
+
+        while line_start < text.len() {
+            line_number += 1;
+            let line_end = memchr::memchr(b'\n', &text[line_start..])
+                .map(|i| line_start + i + 1)
+                .unwrap_or(text.len());
+            let mut content_end = line_end;
+            if content_end > line_start && text[content_end - 1] == b'\n' {
+                content_end -= 1;
+                if content_end > line_start && text[content_end - 1] == b'\r' {
+                    content_end -= 1;
+                }
+            }
+
+            let line = &text[line_start..line_end];
+            let in_scope = self.range.as_ref().is_none_or(|r| {
+                line_number >= r.start && r.end.is_none_or(|end| line_number <= end)
+            });
+            let under_limit = self.max_replacements == 0 || count < self.max_replacements;
+
+            let affected = in_scope
+                && under_limit
+                && match action {
+                    LineAction::Delete | LineAction::Replace => {
+                        self.line_matches(text, line_start, content_end, &mut allowed_cursor)
+                    }
+                    LineAction::Keep => {
+                        !self.line_matches(text, line_start, content_end, &mut allowed_cursor)
+                    }
+                };
+
+            if affected {
+                count += 1;
+                if action == LineAction::Replace {
+                    new_data.extend_from_slice(&self.replacement);
+                    new_data.extend_from_slice(&text[content_end..line_end]);
+                }
+            } else {
+                new_data.extend_from_slice(line);
+            }
+
+            line_start = line_end;
+        }
+
+        if count == 0 {
+            return (Cow::Borrowed(text), 0);
+        }
+        (Cow::Owned(new_data), count)
+    }
+
+    /// Apply a line-oriented action to one line (including its terminator), ignoring the
+    /// configured line range and limit. Returns the new content if the line is affected:
+    /// empty for a dropped line, the replacement plus the original terminator otherwise.
+    pub fn apply_to_line(&self, line: &[u8], action: LineAction) -> Option<Vec<u8>> {
+        let (content, terminator) = split_terminator(line);
+        let matched = self.line_matches(content, 0, content.len(), &mut 0);
+        match action {
+            LineAction::Delete if matched => Some(Vec::new()),
+            LineAction::Keep if !matched => Some(Vec::new()),
+            LineAction::Replace if matched => {
+                let mut out = self.replacement.clone();
+                out.extend_from_slice(terminator);
+                Some(out)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check whether the line `text[start..end]` contains an eligible match.
+    fn line_matches(&self, text: &[u8], start: usize, end: usize, cursor: &mut usize) -> bool {
+        let line = &text[start..end];
+        let eligible = |m_start: usize, m_end: usize, cursor: &mut usize| match &self.allowed_ranges
+        {
+            Some(allowed) => {
+                check_allowed_range_optimized(start + m_start, start + m_end, allowed, cursor)
+            }
+            None => true,
+        };
+
+        match &self.matcher {
+            Matcher::Regex(re) | Matcher::Flexible { regex: re, .. } => re
+                .find_iter(line)
+                .any(|m| eligible(m.start(), m.end(), cursor)),
+            Matcher::Literal(needle) => {
+                memmem::find_iter(line, needle).any(|m| eligible(m, m + needle.len(), cursor))
+            }
+            #[cfg(feature = "fancy")]
+            Matcher::Fancy { .. } => unreachable!("line operations use the default engine"),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use super::{build_line_offsets, check_allowed_range_optimized, is_in_range};
+use crate::eol;
+use crate::error::{Error, Result};
+use crate::model::{LineEnding, LineRange, ReplacementRange};
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Replaces several literal keys in one simultaneous pass.
+///
+/// At each position the longest key wins, and replaced text is never matched
+/// again, so `foo -> bar` and `bar -> foo` swap the two words.
+#[derive(Clone)]
+pub struct MapReplacer {
+    automaton: AhoCorasick,
+    keys: Vec<String>,
+    replacements: Vec<Vec<u8>>,
+    max_replacements: usize,
+    range: Option<LineRange>,
+    /// Byte ranges (e.g. ripgrep matches) a replaced key must overlap, sorted.
+    allowed_ranges: Option<Vec<ReplacementRange>>,
+    /// Only replace keys that start and end on a word boundary (`Some(unicode)`).
+    word: Option<bool>,
+}
+
+impl MapReplacer {
+    /// Build the automaton for `entries`. `ignore_case` only folds ASCII letters.
+    pub fn new<'e>(
+        entries: impl IntoIterator<Item = (&'e String, &'e String)>,
+        ignore_case: bool,
+        max_replacements: usize,
+        range: Option<LineRange>,
+    ) -> Result<Self> {
+        let (keys, replacements): (Vec<String>, Vec<Vec<u8>>) = entries
+            .into_iter()
+            .map(|(key, value)| (key.clone(), value.as_bytes().to_vec()))
+            .unzip();
+        if keys.iter().any(|key| key.is_empty()) {
+            return Err(Error::Validation(
+                "replace_map keys must not be empty".into(),
+            ));
+        }
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostLongest)
+            .ascii_case_insensitive(ignore_case)
+            .build(&keys)
+            .map_err(|e| Error::Validation(format!("Invalid replace_map: {}", e)))?;
+        Ok(Self {
+            automaton,
+            keys,
+            replacements,
+            max_replacements,
+            range,
+            allowed_ranges: None,
+            word: None,
+        })
+    }
+
+    /// A copy of this replacer that only touches keys overlapping `ranges`.
+    pub fn with_allowed_ranges(&self, ranges: &[ReplacementRange]) -> Self {
+        let mut ranges = ranges.to_vec();
+        ranges.sort();
+        Self {
+            allowed_ranges: Some(ranges),
+            ..self.clone()
+        }
+    }
+
+    /// Only replace keys that are whole words, like `\b` around a regex. With `unicode`
+    /// off, only ASCII letters, digits and `_` are word characters.
+    pub fn with_word(mut self, word: bool, unicode: bool) -> Self {
+        self.word = word.then_some(unicode);
+        self
+    }
+
+    /// Rewrite the newlines of every replacement to `eol`.
+    pub fn with_eol(mut self, eol: LineEnding) -> Self {
+        for replacement in &mut self.replacements {
+            if let (Cow::Owned(bytes), _) = eol::normalize(replacement, eol) {
+                *replacement = bytes;
+            }
+        }
+        self
+    }
+
+    /// Add `counts` to the running per-key totals and return their sum.
+    pub fn tally(&self, counts: Vec<usize>, totals: &mut BTreeMap<String, usize>) -> usize {
+        let mut sum = 0;
+        for (key, count) in self.keys.iter().zip(counts) {
+            *totals.entry(key.clone()).or_default() += count;
+            sum += count;
+        }
+        sum
+    }
+
+    /// The line range replacements are restricted to.
+    pub fn range(&self) -> Option<&LineRange> {
+        self.range.as_ref()
+    }
+
+    /// The maximum number of replacements across all keys (0 = unlimited).
+    pub fn limit(&self) -> usize {
+        self.max_replacements
+    }
+
+    /// Replace every key and return the new text along with the count for each key,
+    /// in key order (see `tally`).
+    pub fn replace_with_counts<'a>(&self, text: &'a [u8]) -> (Cow<'a, [u8]>, Vec<usize>) {
+        self.replace_bounded(text, self.max_replacements, self.range.as_ref())
+    }
+
+    /// Replace keys in a single line, ignoring the configured line range and limit.
+    pub fn replace_in_line<'a>(&self, line: &'a [u8], limit: usize) -> (Cow<'a, [u8]>, Vec<usize>) {
+        self.replace_bounded(line, limit, None)
+    }
+
+    fn replace_bounded<'a>(
+        &self,
+        text: &'a [u8],
+        limit: usize,
+        range: Option<&LineRange>,
+    ) -> (Cow<'a, [u8]>, Vec<usize>) {
+        let mut counts = vec![0; self.keys.len()];
+        let mut out = Vec::new();
+        let mut last_match_end = 0;
+        let mut total = 0;
+
+        let line_offsets = range.map(|range| (range, build_line_offsets(text)));
+        let mut allowed_cursor = 0;
+        let mut at = 0;
+        while let Some(m) = self.automaton.find(Input::new(text).range(at..)) {
+            if limit != 0 && total >= limit {
+                break;
+            }
+            if let Some(unicode) = self.word {
+                if !is_word_boundary(text, m.start(), unicode)
+                    || !is_word_boundary(text, m.end(), unicode)
+                {
+                    // A shorter key may still be a whole word further on
+                    at = m.start() + 1;
+                    continue;
+                }
+            }
+            at = m.end();
+            let in_range = line_offsets
+                .as_ref()
+                .is_none_or(|(range, offsets)| is_in_range(m.start(), range, offsets));
+            let allowed = self.allowed_ranges.as_ref().is_none_or(|allowed| {
+                check_allowed_range_optimized(m.start(), m.end(), allowed, &mut allowed_cursor)
+            });
+            if !in_range || !allowed {
+                continue;
+            }
+            if out.capacity() == 0 {
+                out.reserve(text.len());
+            }
+            out.extend_from_slice(&text[last_match_end..m.start()]);
+            out.extend_from_slice(&self.replacements[m.pattern().as_usize()]);
+            last_match_end = m.end();
+            counts[m.pattern().as_usize()] += 1;
+            total += 1;
+        }
+
+        if total == 0 {
+            return (Cow::Borrowed(text), counts);
+        }
+        out.extend_from_slice(&text[last_match_end..]);
+        (Cow::Owned(out), counts)
+    }
+}
+
+/// Whether exactly one side of byte offset `at` is a word character.
+fn is_word_boundary(text: &[u8], at: usize, unicode: bool) -> bool {
+    // A UTF-8 character is at most 4 bytes long
+    let before = text[at.saturating_sub(4)..at]
+        .utf8_chunks()
+        .last()
+        .filter(|chunk| chunk.invalid().is_empty())
+        .and_then(|chunk| chunk.valid().chars().last());
+    let after = text[at..text.len().min(at + 4)]
+        .utf8_chunks()
+        .next()
+        .and_then(|chunk| chunk.valid().chars().next());
+    let is_word = |c: Option<char>| {
+        c.is_some_and(|c| {
+            c == '_'
+                || if unicode {
+                    c.is_alphanumeric()
+                } else {
+                    c.is_ascii_alphanumeric()
+                }
+        })
+    };
+    is_word(before) != is_word(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn swaps_in_one_pass() {
+        let entries = map(&[("foo", "bar"), ("bar", "foo")]);
+        let replacer = MapReplacer::new(&entries, false, 0, None).unwrap();
+        let (out, counts) = replacer.replace_with_counts(b"foo bar foo");
+        assert_eq!(&out[..], b"bar foo bar");
+        // Counts follow the key order: "bar", then "foo"
+        assert_eq!(counts, vec![1, 2]);
+    }
+
+    #[test]
+    fn longest_key_wins() {
+        let entries = map(&[("a", "1"), ("ab", "2"), ("abc", "3")]);
+        let replacer = MapReplacer::new(&entries, false, 0, None).unwrap();
+        let (out, counts) = replacer.replace_with_counts(b"abcab a");
+        assert_eq!(&out[..], b"32 1");
+        assert_eq!(counts, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn honors_range_limit_and_case() {
+        let entries = map(&[("x", "y")]);
+        let range = Some(LineRange {
+            start: 2,
+            end: None,
+        });
+        let replacer = MapReplacer::new(&entries, true, 2, range).unwrap();
+        let (out, counts) = replacer.replace_with_counts(b"x\nX x x\n");
+        assert_eq!(&out[..], b"x\ny y x\n");
+        assert_eq!(counts, vec![2]);
+    }
+
+    #[test]
+    fn word_only_replaces_whole_words() {
+        let entries = map(&[("user", "account"), ("user_id", "account_id")]);
+        let replacer = MapReplacer::new(&entries, false, 0, None)
+            .unwrap()
+            .with_word(true, true);
+        let (out, counts) = replacer.replace_with_counts("username user_id user éuser".as_bytes());
+        assert_eq!(&out[..], "username account_id account éuser".as_bytes());
+        assert_eq!(counts, vec![1, 1]);
+
+        let replacer = replacer.with_word(true, false);
+        let (out, _) = replacer.replace_with_counts("éuser".as_bytes());
+        assert_eq!(&out[..], "éaccount".as_bytes());
+    }
+
+    #[test]
+    fn allowed_ranges_limit_replacements() {
+        let entries = map(&[("foo", "bar")]);
+        let replacer = MapReplacer::new(&entries, false, 0, None)
+            .unwrap()
+            .with_allowed_ranges(&[ReplacementRange { start: 8, end: 11 }]);
+        let (out, counts) = replacer.replace_with_counts(b"foo one\nfoo two\n");
+        assert_eq!(&out[..], b"foo one\nbar two\n");
+        assert_eq!(counts, vec![1]);
+    }
+
+    #[test]
+    fn rejects_empty_keys() {
+        let entries = map(&[("", "x")]);
+        assert!(MapReplacer::new(&entries, false, 0, None).is_err());
+    }
+}
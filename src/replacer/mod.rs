@@ -1,23 +1,92 @@
+use crate::eol;
 use crate::error::{Error, Result};
-use crate::model::{LineRange, ReplacementRange, ValidationMode};
+use crate::model::{
+    LineEnding, LineRange, Occurrence, ReplacementRange, ValidationMode, Whitespace,
+};
 use memchr::memmem;
-use regex::bytes::{NoExpand, Regex, RegexBuilder};
+use regex::bytes::{Regex, RegexBuilder};
+use regex_automata::util::syntax;
 use std::borrow::Cow;
 
+mod block;
+mod case;
+mod context;
+mod ensure;
+mod lines;
+mod map;
+mod template;
 mod validate;
 
+pub use block::BlockReplacer;
+pub use case::case_variants;
+use context::Context;
+pub use ensure::Ensurer;
+pub use lines::{split_terminator, LineAction};
+pub use map::MapReplacer;
+pub use template::TemplateVars;
+use template::{Groups, MatchVars, Template};
+
+#[derive(Clone)]
 enum Matcher {
     Regex(Regex),
     Literal(Vec<u8>),
+    /// A literal whose whitespace runs match any whitespace run.
+    Flexible {
+        regex: Regex,
+        /// Indentation of the replacement's first line. Later lines keep their indentation
+        /// relative to it, rebased on the indentation of the matched line.
+        indent: Vec<u8>,
+    },
+    #[cfg(feature = "fancy")]
+    Fancy {
+        regex: fancy_regex::Regex,
+        backtrack_limit: usize,
+    },
+}
+
+/// The regex engine a pattern is compiled with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Regex,
+    /// `fancy-regex`, which fails a search after `backtrack_limit` backtracking steps.
+    #[cfg(feature = "fancy")]
+    Fancy { backtrack_limit: usize },
+}
+
+/// A match, with its capture groups when the replacement needs them.
+struct Found<G> {
+    start: usize,
+    end: usize,
+    groups: Option<G>,
+}
+
+/// Where the replacement text goes relative to each match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    /// Substitute the match with the replacement text.
+    #[default]
+    Replace,
+    /// Keep the match and insert the text in front of it.
+    InsertBefore,
+    /// Keep the match and insert the text behind it.
+    InsertAfter,
 }
 
+#[derive(Clone)]
 pub struct Replacer {
     matcher: Matcher,
     replacement: Vec<u8>,
+    placement: Placement,
     max_replacements: usize,
     range: Option<LineRange>,
     allowed_ranges: Option<Vec<ReplacementRange>>,
-    expand: bool,
+    occurrence: Option<Occurrence>,
+    context: Option<Box<Context>>,
+    /// The flags `find` was compiled with, which `before` and `after` share.
+    syntax: syntax::Config,
+    /// Set when an expanded replacement refers to capture groups or variables.
+    template: Option<Template>,
 }
 
 impl Replacer {
@@ -38,7 +107,20 @@ impl Replacer {
         allowed_ranges: Option<Vec<ReplacementRange>>,
         expand: bool,
         validation_mode: ValidationMode,
+        engine: Engine,
+        whitespace: Whitespace,
     ) -> Result<Self> {
+        let flexible = whitespace == Whitespace::Flexible;
+        // Leading whitespace is ignored in `pattern`, so it is dropped from the replacement too
+        let (replacement, indent) = if flexible {
+            let trimmed = replacement.trim_start();
+            let leading = &replacement[..replacement.len() - trimmed.len()];
+            let indent = leading.rsplit('\n').next().unwrap_or_default();
+            (trimmed, indent.as_bytes().to_vec())
+        } else {
+            (replacement, Vec::new())
+        };
+
         // 1. Validate replacement pattern for capture group references
         if !expand {
             // If we don't expand, we don't strictly need to validate $1, but it might be nice to warn?
@@ -61,29 +143,50 @@ impl Replacer {
             Cow::Borrowed(replacement)
         };
 
-        // Use validated_replacement for check below
+        // An expansion that is the same for every match is plain text
+        let (replacement_bytes, template) =
+            match expand.then(|| Template::parse(&validated_replacement)) {
+                Some(template) => match template.as_literal() {
+                    Some(text) => (text, None),
+                    None => (validated_replacement.as_bytes().to_vec(), Some(template)),
+                },
+                None => (validated_replacement.as_bytes().to_vec(), None),
+            };
 
         // Determine if we can use efficient literal matcher
         // We can use Literal matcher only if:
         // - fixed_strings is requested (or pattern is literal) -> handled by caller passing fixed_strings
         // - NO regex flags that affect matching (ignore_case, smart_case, word_regexp, multiline etc)
-        // - NO expansion (if expand is true, we need regex engine to resolve captures, UNLESS replacement has no $ signs)
+        // - NO template (if the replacement refers to captures, we need a regex engine to resolve them)
         // Note: multiline/dot_matches_newline don't apply to literal strings unless we search line by line?
         // memmem works on bytes, ignores lines.
         // word_regexp requires checking boundaries -> complex for memmem, use regex.
         // ignore_case -> complex for memmem, use regex.
 
+        // Case handling
+        let case_insensitive =
+            ignore_case || (smart_case && pattern.chars().all(|c| !c.is_uppercase()));
+        let syntax = syntax::Config::new()
+            .utf8(false)
+            .unicode(!no_unicode)
+            .case_insensitive(case_insensitive)
+            .multi_line(multiline && !single_line)
+            .dot_matches_new_line(dot_matches_newline);
+
         let use_literal_matcher = fixed_strings
+            && !flexible
             && !ignore_case
             && !smart_case
             && !word_regexp
-            && (!expand || !validated_replacement.contains("$")); // If expansion requested but no $ involved, literal is fine
+            && template.is_none();
 
         let matcher = if use_literal_matcher {
             Matcher::Literal(pattern.as_bytes().to_vec())
         } else {
             // Build regex
-            let pattern = if fixed_strings {
+            let pattern = if flexible {
+                flexible_pattern(pattern)?
+            } else if fixed_strings {
                 regex::escape(pattern)
             } else {
                 pattern.to_string()
@@ -95,28 +198,39 @@ impl Replacer {
                 pattern
             };
 
-            let mut builder = RegexBuilder::new(&pattern);
-            builder.unicode(!no_unicode);
-
-            // Case handling
-            if ignore_case {
-                builder.case_insensitive(true);
-            } else if smart_case {
-                let is_lowercase = pattern.chars().all(|c| !c.is_uppercase());
-                builder.case_insensitive(is_lowercase);
-            } else {
-                builder.case_insensitive(false);
+            match engine {
+                Engine::Regex => {
+                    let regex = RegexBuilder::new(&pattern)
+                        .unicode(!no_unicode)
+                        .case_insensitive(case_insensitive)
+                        .multi_line(multiline && !single_line)
+                        .dot_matches_new_line(dot_matches_newline)
+                        .build()
+                        .map_err(Error::Regex)?;
+                    if flexible {
+                        Matcher::Flexible { regex, indent }
+                    } else {
+                        Matcher::Regex(regex)
+                    }
+                }
+                #[cfg(feature = "fancy")]
+                Engine::Fancy { backtrack_limit } => {
+                    let regex = fancy_regex::RegexBuilder::new(&pattern)
+                        .unicode_mode(!no_unicode)
+                        .case_insensitive(case_insensitive)
+                        .multi_line(multiline && !single_line)
+                        .dot_matches_new_line(dot_matches_newline)
+                        .backtrack_limit(backtrack_limit)
+                        .build()
+                        .map_err(|e| Error::Validation(format!("Invalid regex: {}", e)))?;
+                    Matcher::Fancy {
+                        regex,
+                        backtrack_limit,
+                    }
+                }
             }
-
-            builder.multi_line(multiline && !single_line);
-            builder.dot_matches_new_line(dot_matches_newline);
-
-            let regex = builder.build().map_err(Error::Regex)?;
-            Matcher::Regex(regex)
         };
 
-        let replacement_bytes = validated_replacement.as_bytes().to_vec();
-
         let mut allowed_ranges = allowed_ranges;
         if let Some(ref mut ranges) = allowed_ranges {
             ranges.sort();
@@ -125,195 +239,427 @@ impl Replacer {
         Ok(Self {
             matcher,
             replacement: replacement_bytes,
+            placement: Placement::default(),
             max_replacements,
             range,
             allowed_ranges,
-            expand,
+            occurrence: None,
+            context: None,
+            syntax,
+            template,
         })
     }
 
-    /// Count the number of matches in the given text.
-    pub fn count_matches(&self, text: &[u8]) -> usize {
-        if self.range.is_some() || self.allowed_ranges.is_some() {
-            // If range filters are set, we must iterate to check bounds
-            let mut count = 0;
-            let line_offsets = if self.range.is_some() {
-                Some(build_line_offsets(text))
-            } else {
-                None
-            };
+    /// Set where the replacement text is placed relative to each match.
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
 
-            let mut allowed_cursor = 0;
-
-            match &self.matcher {
-                Matcher::Regex(re) => {
-                    for m in re.find_iter(text) {
-                        if let Some(range) = &self.range {
-                            if !is_in_range(m.start(), range, line_offsets.as_ref().unwrap()) {
-                                continue;
-                            }
-                        }
-                        if let Some(allowed) = &self.allowed_ranges {
-                            if !check_allowed_range_optimized(
-                                m.start(),
-                                m.end(),
-                                allowed,
-                                &mut allowed_cursor,
-                            ) {
-                                continue;
-                            }
-                        }
-                        count += 1;
-                    }
-                }
-                Matcher::Literal(needle) => {
-                    for m in memmem::find_iter(text, needle) {
-                        if let Some(range) = &self.range {
-                            if !is_in_range(m, range, line_offsets.as_ref().unwrap()) {
-                                continue;
-                            }
-                        }
-                        let end = m + needle.len();
-                        if let Some(allowed) = &self.allowed_ranges {
-                            if !check_allowed_range_optimized(m, end, allowed, &mut allowed_cursor)
-                            {
-                                continue;
-                            }
-                        }
-                        count += 1;
-                    }
-                }
+    /// Only replace the selected matches, numbered after the range filters.
+    pub fn with_occurrence(mut self, occurrence: Option<Occurrence>) -> Self {
+        self.occurrence = occurrence;
+        self
+    }
+
+    /// Only replace matches immediately preceded by `before` and followed by `after`.
+    /// Both are read literally if `literal` and otherwise with the flags of the pattern.
+    pub fn with_context(
+        mut self,
+        before: Option<&str>,
+        after: Option<&str>,
+        literal: bool,
+    ) -> Result<Self> {
+        if before.is_none() && after.is_none() {
+            return Ok(self);
+        }
+        let pattern = |pattern: &str| {
+            if literal {
+                regex::escape(pattern)
+            } else {
+                pattern.to_string()
             }
-            return count;
+        };
+        self.context = Some(Box::new(Context::new(
+            before.map(pattern).as_deref(),
+            after.map(pattern).as_deref(),
+            self.syntax,
+        )?));
+        Ok(self)
+    }
+
+    /// Rewrite the newlines of the replacement text to `eol`.
+    pub fn with_eol(mut self, eol: LineEnding) -> Self {
+        if let (Cow::Owned(bytes), _) = eol::normalize(&self.replacement, eol) {
+            self.replacement = bytes;
+        }
+        self.template = self.template.map(|template| template.with_eol(eol));
+        self
+    }
+
+    /// A copy of this replacer that only touches matches overlapping `ranges`.
+    pub fn with_allowed_ranges(&self, ranges: &[ReplacementRange]) -> Self {
+        let mut ranges = ranges.to_vec();
+        ranges.sort();
+        Self {
+            allowed_ranges: Some(ranges),
+            ..self.clone()
         }
+    }
 
+    /// All match spans of the pattern, ignoring limits and scope filters.
+    /// Only the `fancy` engine can fail while searching.
+    fn spans<'t>(
+        &'t self,
+        text: &'t [u8],
+    ) -> Box<dyn Iterator<Item = Result<(usize, usize)>> + 't> {
         match &self.matcher {
-            Matcher::Regex(re) => re.find_iter(text).count(),
-            Matcher::Literal(needle) => memmem::find_iter(text, needle).count(),
+            Matcher::Regex(re) | Matcher::Flexible { regex: re, .. } => {
+                Box::new(re.find_iter(text).map(|m| Ok((m.start(), m.end()))))
+            }
+            Matcher::Literal(needle) => {
+                Box::new(memmem::find_iter(text, needle).map(|m| Ok((m, m + needle.len()))))
+            }
+            #[cfg(feature = "fancy")]
+            Matcher::Fancy {
+                regex,
+                backtrack_limit,
+            } => match fancy_haystack(text) {
+                Ok(haystack) => Box::new(regex.find_iter(haystack).map(|m| {
+                    m.map(|m| (m.start(), m.end()))
+                        .map_err(|e| fancy_error(e, *backtrack_limit))
+                })),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            },
         }
     }
 
     /// Replace matches in text and return the replaced text along with the number of replacements performed.
-    pub fn replace_with_count<'a>(&self, text: &'a [u8]) -> (Cow<'a, [u8]>, usize) {
-        // If no range filter and regex replacement, use regex methods for speed
-        if self.range.is_none() && self.allowed_ranges.is_none() {
-            if let Matcher::Regex(re) = &self.matcher {
-                let matches_count = self.count_matches(text);
-                if matches_count == 0 {
-                    return (Cow::Borrowed(text), 0);
-                }
-                let actual_replacements = if self.max_replacements == 0 {
-                    matches_count
-                } else {
-                    std::cmp::min(matches_count, self.max_replacements)
-                };
-                if actual_replacements == 0 {
-                    return (Cow::Borrowed(text), 0);
-                }
+    ///
+    /// The text is scanned once: every eligible match is counted as it is spliced into the output,
+    /// and nothing is allocated until the first one is found.
+    ///
+    /// `vars` fills in the reserved template variables.
+    pub fn replace_with_count<'a>(
+        &self,
+        text: &'a [u8],
+        vars: &TemplateVars,
+    ) -> Result<(Cow<'a, [u8]>, usize)> {
+        self.replace_bounded(text, self.max_replacements, self.range.as_ref(), vars)
+    }
 
-                let replaced = if self.max_replacements == 0 {
-                    if self.expand {
-                        re.replace_all(text, &self.replacement[..])
-                    } else {
-                        re.replace_all(text, NoExpand(&self.replacement))
-                    }
-                } else if self.expand {
-                    re.replacen(text, self.max_replacements, &self.replacement[..])
-                } else {
-                    re.replacen(text, self.max_replacements, NoExpand(&self.replacement))
-                };
-                return (replaced, actual_replacements);
+    /// Replace matches in a single line, ignoring the configured line range and limit.
+    /// Used when streaming, where the caller tracks line numbers and the remaining
+    /// `limit` (0 = unlimited) itself.
+    pub fn replace_in_line<'a>(
+        &self,
+        line: &'a [u8],
+        limit: usize,
+        vars: &TemplateVars,
+    ) -> Result<(Cow<'a, [u8]>, usize)> {
+        self.replace_bounded(line, limit, None, vars)
+    }
+
+    /// Whether the replacement uses `${__global}`, which numbers matches across inputs.
+    #[cfg(feature = "parallel")]
+    pub fn uses_global(&self) -> bool {
+        self.template.as_ref().is_some_and(Template::uses_global)
+    }
+
+    /// The line range operations are restricted to.
+    pub fn range(&self) -> Option<&LineRange> {
+        self.range.as_ref()
+    }
+
+    /// The maximum number of replacements (0 = unlimited).
+    pub fn limit(&self) -> usize {
+        self.max_replacements
+    }
+
+    fn replace_bounded<'a>(
+        &self,
+        text: &'a [u8],
+        limit: usize,
+        range: Option<&LineRange>,
+        vars: &TemplateVars,
+    ) -> Result<(Cow<'a, [u8]>, usize)> {
+        match &self.matcher {
+            // Captures are only resolved when the replacement refers to them
+            Matcher::Regex(re) | Matcher::Flexible { regex: re, .. } if self.template.is_some() => {
+                let found = re.captures_iter(text).map(|caps| {
+                    let m = caps.get(0).unwrap();
+                    Ok(Found {
+                        start: m.start(),
+                        end: m.end(),
+                        groups: Some(caps),
+                    })
+                });
+                self.splice_all(text, found, limit, range, vars)
+            }
+            #[cfg(feature = "fancy")]
+            Matcher::Fancy {
+                regex,
+                backtrack_limit,
+            } if self.template.is_some() => {
+                let found = regex.captures_iter(fancy_haystack(text)?).map(|caps| {
+                    let caps = caps.map_err(|e| fancy_error(e, *backtrack_limit))?;
+                    let m = caps.get(0).unwrap();
+                    Ok(Found {
+                        start: m.start(),
+                        end: m.end(),
+                        groups: Some(caps),
+                    })
+                });
+                self.splice_all(text, found, limit, range, vars)
+            }
+            _ => {
+                let found = self.spans(text).map(|span| {
+                    span.map(|(start, end)| Found::<regex::bytes::Captures> {
+                        start,
+                        end,
+                        groups: None,
+                    })
+                });
+                self.splice_all(text, found, limit, range, vars)
             }
         }
+    }
 
-        // Manual replacement loop required for:
-        // 1. Literal matcher (no replace_all)
-        // 2. Range filtering (must check each match)
-
-        let mut new_data = Vec::with_capacity(text.len());
+    /// Splice the eligible and selected matches of `found`, in order, into a copy of `text`.
+    fn splice_all<'a, G: Groups>(
+        &self,
+        text: &'a [u8],
+        found: impl Iterator<Item = Result<Found<G>>>,
+        limit: usize,
+        range: Option<&LineRange>,
+        vars: &TemplateVars,
+    ) -> Result<(Cow<'a, [u8]>, usize)> {
+        let mut new_data = Vec::new();
         let mut last_match_end = 0;
         let mut count = 0;
 
-        let line_offsets = if self.range.is_some() {
-            Some(build_line_offsets(text))
-        } else {
-            None
+        let mut eligible = self.eligibility(text, range);
+        // Matches are numbered after the range filters, so the selection sees only eligible ones
+        let (first, last) = match &self.occurrence {
+            Some(occurrence) if occurrence.counts_from_end() => {
+                let mut eligible = self.eligibility(text, range);
+                let mut total = 0;
+                for span in self.spans(text) {
+                    let (start, end) = span?;
+                    total += usize::from(eligible(start, end));
+                }
+                occurrence.resolve(total)
+            }
+            Some(occurrence) => occurrence.resolve(0),
+            None => (1, usize::MAX),
+        };
+        let mut ordinal = 0;
+        let under_limit = |count: usize| limit == 0 || count < limit;
+        let needs_position = self.template.as_ref().is_some_and(Template::uses_position);
+        let mut position = Position::default();
+        let reindent = match &self.matcher {
+            Matcher::Flexible { indent, .. } => Some(indent.as_slice()),
+            _ => None,
         };
 
-        let mut allowed_cursor = 0;
-
-        match &self.matcher {
-            Matcher::Regex(re) => {
-                for m in re.captures_iter(text) {
-                    if self.max_replacements > 0 && count >= self.max_replacements {
-                        break;
+        for found in found {
+            if !under_limit(count) {
+                break;
+            }
+            let Found { start, end, groups } = found?;
+            if !eligible(start, end) {
+                continue;
+            }
+            ordinal += 1;
+            if ordinal > last {
+                break;
+            }
+            if ordinal < first {
+                continue;
+            }
+            self.splice(&mut new_data, text, last_match_end, start, end, |out| {
+                let from = out.len();
+                match (&self.template, &groups) {
+                    (Some(template), Some(groups)) => {
+                        let vars = MatchVars {
+                            vars,
+                            position: if needs_position {
+                                position.locate(text, start)
+                            } else {
+                                (0, 0)
+                            },
+                            index: count,
+                        };
+                        template.expand(groups, &vars, out)
                     }
+                    _ => out.extend_from_slice(&self.replacement),
+                }
+                if let Some(base) = reindent {
+                    reindent_lines(out, from, base, line_indent(text, start));
+                }
+            });
+            last_match_end = end;
+            count += 1;
+        }
 
-                    let match_start = m.get(0).unwrap().start();
-                    let match_end = m.get(0).unwrap().end();
+        if count == 0 {
+            return Ok((Cow::Borrowed(text), 0));
+        }
 
-                    if let Some(range) = &self.range {
-                        if !is_in_range(match_start, range, line_offsets.as_ref().unwrap()) {
-                            continue;
-                        }
-                    }
+        new_data.extend_from_slice(&text[last_match_end..]);
+        Ok((Cow::Owned(new_data), count))
+    }
 
-                    if let Some(allowed) = &self.allowed_ranges {
-                        if !check_allowed_range_optimized(
-                            match_start,
-                            match_end,
-                            allowed,
-                            &mut allowed_cursor,
-                        ) {
-                            continue;
-                        }
-                    }
+    /// 1-based line and byte column of every match that passes the range and context filters.
+    /// `limit` and the occurrence selection are ignored.
+    pub fn locate_matches(&self, text: &[u8]) -> Result<Vec<(usize, usize)>> {
+        let mut eligible = self.eligibility(text, self.range.as_ref());
+        let mut position = Position::default();
+        let mut locations = Vec::new();
+        for span in self.spans(text) {
+            let (start, end) = span?;
+            if eligible(start, end) {
+                locations.push(position.locate(text, start));
+            }
+        }
+        Ok(locations)
+    }
 
-                    new_data.extend_from_slice(&text[last_match_end..match_start]);
+    /// A filter for match spans outside the line `range` or `allowed_ranges`,
+    /// or without the required context. Spans must be passed in order.
+    fn eligibility<'s>(
+        &'s self,
+        text: &'s [u8],
+        range: Option<&'s LineRange>,
+    ) -> impl FnMut(usize, usize) -> bool + 's {
+        let line_offsets = range.map(|range| (range, build_line_offsets(text)));
+        let mut allowed_cursor = 0;
+        move |start, end| {
+            let in_range = line_offsets
+                .as_ref()
+                .is_none_or(|(range, offsets)| is_in_range(start, range, offsets));
+            in_range
+                && self.is_allowed(start, end, &mut allowed_cursor)
+                && self
+                    .context
+                    .as_ref()
+                    .is_none_or(|context| context.surrounds(text, start, end))
+        }
+    }
 
-                    if self.expand {
-                        m.expand(&self.replacement, &mut new_data);
-                    } else {
-                        new_data.extend_from_slice(&self.replacement);
-                    }
+    /// Check a match against the allowed ranges.
+    /// Matches must be checked in order, as the cursor only moves forward.
+    fn is_allowed(&self, start: usize, end: usize, allowed_cursor: &mut usize) -> bool {
+        match &self.allowed_ranges {
+            Some(allowed) => check_allowed_range_optimized(start, end, allowed, allowed_cursor),
+            None => true,
+        }
+    }
 
-                    last_match_end = match_end;
-                    count += 1;
-                }
-            }
-            Matcher::Literal(needle) => {
-                for m in memmem::find_iter(text, needle) {
-                    if self.max_replacements > 0 && count >= self.max_replacements {
-                        break;
-                    }
+    /// Copy the text since the previous match, then the match itself as placed by the replacement.
+    fn splice(
+        &self,
+        out: &mut Vec<u8>,
+        text: &[u8],
+        last_match_end: usize,
+        start: usize,
+        end: usize,
+        replacement: impl FnOnce(&mut Vec<u8>),
+    ) {
+        if out.capacity() == 0 {
+            out.reserve(text.len());
+        }
+        out.extend_from_slice(&text[last_match_end..start]);
+        if self.placement == Placement::InsertAfter {
+            out.extend_from_slice(&text[start..end]);
+        }
+        replacement(out);
+        if self.placement == Placement::InsertBefore {
+            out.extend_from_slice(&text[start..end]);
+        }
+    }
+}
 
-                    if let Some(range) = &self.range {
-                        if !is_in_range(m, range, line_offsets.as_ref().unwrap()) {
-                            continue;
-                        }
-                    }
+/// A regex matching `find` literally, except that each whitespace run matches any whitespace run.
+/// Leading whitespace is ignored, and trailing line breaks match line breaks that may follow
+/// trailing whitespace.
+fn flexible_pattern(find: &str) -> Result<String> {
+    let words: Vec<String> = find.split_whitespace().map(regex::escape).collect();
+    if words.is_empty() {
+        return Err(Error::Validation(
+            "find must contain non-whitespace text when whitespace is flexible".into(),
+        ));
+    }
+    let trailing = &find[find.trim_end().len()..];
+    let line_breaks = trailing.matches('\n').count();
+    Ok(words.join(r"\s+") + &r"[^\S\n]*\n".repeat(line_breaks))
+}
 
-                    let end = m + needle.len();
-                    if let Some(allowed) = &self.allowed_ranges {
-                        if !check_allowed_range_optimized(m, end, allowed, &mut allowed_cursor) {
-                            continue;
-                        }
-                    }
+/// The indentation of the line containing `offset`.
+fn line_indent(text: &[u8], offset: usize) -> &[u8] {
+    let line_start = memchr::memrchr(b'\n', &text[..offset]).map_or(0, |i| i + 1);
+    let width = text[line_start..]
+        .iter()
+        .take_while(|&&b| b == b' ' || b == b'\t')
+        .count();
+    &text[line_start..line_start + width]
+}
 
-                    new_data.extend_from_slice(&text[last_match_end..m]);
-                    new_data.extend_from_slice(&self.replacement);
-                    last_match_end = end;
-                    count += 1;
-                }
-            }
+/// Rebase the lines of `out[from..]` after the first from indentation `base` onto `indent`.
+/// Lines indented less than `base` lose what they share with it; blank lines are left as they are.
+fn reindent_lines(out: &mut Vec<u8>, from: usize, base: &[u8], indent: &[u8]) {
+    if !out[from..].contains(&b'\n') {
+        return;
+    }
+    let replaced = out.split_off(from);
+    let mut lines = replaced.split_inclusive(|&b| b == b'\n');
+    out.extend_from_slice(lines.next().unwrap_or_default());
+    for line in lines {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            out.extend_from_slice(line);
+            continue;
         }
+        let shared = line.iter().zip(base).take_while(|(a, b)| a == b).count();
+        out.extend_from_slice(indent);
+        out.extend_from_slice(&line[shared..]);
+    }
+}
 
-        if count == 0 {
-            return (Cow::Borrowed(text), 0);
+/// The text to search with the `fancy` engine, which only searches `str`.
+#[cfg(feature = "fancy")]
+fn fancy_haystack(text: &[u8]) -> Result<&str> {
+    std::str::from_utf8(text)
+        .map_err(|_| Error::InvalidUtf8("the fancy regex engine only searches valid UTF-8".into()))
+}
+
+#[cfg(feature = "fancy")]
+fn fancy_error(error: fancy_regex::Error, backtrack_limit: usize) -> Error {
+    match error {
+        fancy_regex::Error::RuntimeError(fancy_regex::RuntimeError::BacktrackLimitExceeded) => {
+            Error::BacktrackLimit(backtrack_limit)
         }
+        e => Error::Validation(format!("Regex search failed: {}", e)),
+    }
+}
 
-        new_data.extend_from_slice(&text[last_match_end..]);
-        (Cow::Owned(new_data), count)
+/// Line and column of increasing byte offsets, counted from the previous one.
+#[derive(Default)]
+struct Position {
+    offset: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl Position {
+    /// 1-based line and byte column of `offset`, which must not be before the previous one.
+    fn locate(&mut self, text: &[u8], offset: usize) -> (usize, usize) {
+        let skipped = &text[self.offset..offset];
+        self.line += memchr::memchr_iter(b'\n', skipped).count();
+        if let Some(newline) = memchr::memrchr(b'\n', skipped) {
+            self.line_start = self.offset + newline + 1;
+        }
+        self.offset = offset;
+        (self.line + 1, offset - self.line_start + 1)
     }
 }
 
@@ -406,10 +752,15 @@ mod tests {
             None,
             false,
             ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
         )
         .unwrap();
         let input = b"foo baz foo";
-        let output = replacer.replace_with_count(input).0;
+        let output = replacer
+            .replace_with_count(input, &TemplateVars::default())
+            .unwrap()
+            .0;
         assert_eq!(&output[..], b"bar baz bar");
     }
 
@@ -432,10 +783,15 @@ mod tests {
             None,
             false,
             ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
         )
         .unwrap();
         let input = b"foo baz foo";
-        let output = replacer.replace_with_count(input).0;
+        let output = replacer
+            .replace_with_count(input, &TemplateVars::default())
+            .unwrap()
+            .0;
         assert_eq!(&output[..], b"bar baz bar");
     }
 
@@ -458,10 +814,15 @@ mod tests {
             None,
             false, // expand=false
             ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
         )
         .unwrap();
         let input = b"abc 123 def";
-        let output = replacer.replace_with_count(input).0;
+        let output = replacer
+            .replace_with_count(input, &TemplateVars::default())
+            .unwrap()
+            .0;
         // Should NOT expand $1
         assert_eq!(&output[..], b"abc number-$1 def");
     }
@@ -484,10 +845,15 @@ mod tests {
             None,
             true, // expand=true
             ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
         )
         .unwrap();
         let input = b"abc 123 def";
-        let output = replacer.replace_with_count(input).0;
+        let output = replacer
+            .replace_with_count(input, &TemplateVars::default())
+            .unwrap()
+            .0;
         // Should expand $1
         assert_eq!(&output[..], b"abc number-123 def");
     }
@@ -510,10 +876,15 @@ mod tests {
             None,
             false,
             ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
         )
         .unwrap();
         let input = b"x x x x";
-        let output = replacer.replace_with_count(input).0;
+        let output = replacer
+            .replace_with_count(input, &TemplateVars::default())
+            .unwrap()
+            .0;
         assert_eq!(&output[..], b"y y x x");
     }
 
@@ -544,12 +915,366 @@ mod tests {
             Some(allowed),
             false,
             ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
         )
         .unwrap();
 
         let input = b"x x x";
-        let (output, count) = replacer.replace_with_count(input);
+        let (output, count) = replacer
+            .replace_with_count(input, &TemplateVars::default())
+            .unwrap();
         assert_eq!(count, 2);
         assert_eq!(&output[..], b"y x y");
     }
+
+    #[test]
+    fn test_insert_placement() {
+        let build = |literal: bool, placement: Placement| {
+            Replacer::new(
+                "foo",
+                "[x]",
+                literal,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                1,
+                None,
+                None,
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Exact,
+            )
+            .unwrap()
+            .with_placement(placement)
+        };
+
+        for literal in [true, false] {
+            let (output, count) = build(literal, Placement::InsertBefore)
+                .replace_with_count(b"foo foo", &TemplateVars::default())
+                .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(&output[..], b"[x]foo foo");
+
+            let (output, count) = build(literal, Placement::InsertAfter)
+                .replace_with_count(b"foo foo", &TemplateVars::default())
+                .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(&output[..], b"foo[x] foo");
+        }
+    }
+
+    #[test]
+    fn test_count_matches_output_with_limit_and_range() {
+        let replacer = Replacer::new(
+            r"(\d)",
+            "<$1>",
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+            2,
+            Some(LineRange {
+                start: 2,
+                end: None,
+            }),
+            None,
+            true,
+            ValidationMode::default(),
+            Engine::default(),
+            Whitespace::Exact,
+        )
+        .unwrap();
+        let (output, count) = replacer
+            .replace_with_count(b"1 2\n3 4\n5 6\n", &TemplateVars::default())
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(&output[..], b"1 2\n<3> <4>\n5 6\n");
+
+        let (output, count) = replacer
+            .replace_with_count(b"no digits\n", &TemplateVars::default())
+            .unwrap();
+        assert_eq!(count, 0);
+        assert!(matches!(output, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_occurrence_with_limit_and_allowed_ranges() {
+        let build = |occurrence: Occurrence, limit: usize| {
+            Replacer::new(
+                "x",
+                "y",
+                true,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                limit,
+                None,
+                // The first match is outside the allowed ranges and is not numbered
+                Some(vec![ReplacementRange { start: 2, end: 9 }]),
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Exact,
+            )
+            .unwrap()
+            .with_occurrence(Some(occurrence))
+        };
+        let vars = TemplateVars::default();
+
+        let second = Occurrence {
+            start: 2,
+            end: Some(2),
+        };
+        let (output, count) = build(second, 0)
+            .replace_with_count(b"x x x x x", &vars)
+            .unwrap();
+        assert_eq!((&output[..], count), (&b"x x y x x"[..], 1));
+
+        let last_two = Occurrence {
+            start: -2,
+            end: None,
+        };
+        let (output, count) = build(last_two, 1)
+            .replace_with_count(b"x x x x x", &vars)
+            .unwrap();
+        assert_eq!((&output[..], count), (&b"x x x y x"[..], 1));
+
+        let too_far = Occurrence {
+            start: 9,
+            end: None,
+        };
+        let (output, count) = build(too_far, 0)
+            .replace_with_count(b"x x x x x", &vars)
+            .unwrap();
+        assert_eq!((&output[..], count), (&b"x x x x x"[..], 0));
+    }
+
+    #[test]
+    fn test_context_is_required_but_not_replaced() {
+        let build = |before: Option<&str>, after: Option<&str>| {
+            Replacer::new(
+                "x",
+                "y",
+                true,
+                true,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                0,
+                None,
+                None,
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Exact,
+            )
+            .unwrap()
+            .with_context(before, after, false)
+        };
+        let vars = TemplateVars::default();
+        let text = b"f(x) g(x) F(x, x)";
+
+        let (output, count) = build(Some(r"f\("), None)
+            .unwrap()
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!((&output[..], count), (&b"f(y) g(x) F(y, x)"[..], 2));
+
+        let (output, count) = build(None, Some(r"\)"))
+            .unwrap()
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!((&output[..], count), (&b"f(y) g(y) F(x, y)"[..], 3));
+
+        let (output, count) = build(Some("g[(]"), Some("[)]"))
+            .unwrap()
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!((&output[..], count), (&b"f(x) g(y) F(x, x)"[..], 1));
+
+        assert!(build(Some("("), None).is_err());
+    }
+
+    #[test]
+    fn test_context_assertions_see_surrounding_text() {
+        let build = |find: &str, before: Option<&str>, after: Option<&str>, multiline: bool| {
+            Replacer::new(
+                find,
+                "_",
+                false,
+                false,
+                false,
+                false,
+                multiline,
+                false,
+                false,
+                false,
+                0,
+                None,
+                None,
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Exact,
+            )
+            .unwrap()
+            .with_context(before, after, false)
+            .unwrap()
+        };
+        let replace = |replacer: Replacer, text: &str| {
+            let vars = TemplateVars::default();
+            let (output, _) = replacer.replace_with_count(text.as_bytes(), &vars).unwrap();
+            String::from_utf8(output.into_owned()).unwrap()
+        };
+
+        // `\b` at the edge of the context is checked against the match itself
+        assert_eq!(
+            replace(build("[a-z]+", Some(r"\.\b"), None, false), "a.b .c"),
+            "a._ ._"
+        );
+        assert_eq!(
+            replace(build("-", None, Some(r"\b"), false), "a- -b"),
+            "a- _b"
+        );
+        assert_eq!(
+            replace(build(r"\d", Some(r"\B"), None, false), "x1 2"),
+            "x_ 2"
+        );
+        // Unicode word characters on either side
+        assert_eq!(
+            replace(build("[0-9]", Some(r"é\b"), None, false), "é1 é 2 é3"),
+            "é1 é 2 é3"
+        );
+        assert_eq!(
+            replace(build("x", Some(r"é\B"), None, false), "éx é x"),
+            "é_ é x"
+        );
+        // `$` in `after` only matches at the end of a line with `multiline`
+        assert_eq!(
+            replace(build("b", None, Some("$"), false), "ab\nab"),
+            "ab\na_"
+        );
+        assert_eq!(
+            replace(build("b", None, Some("$"), true), "ab\nab"),
+            "a_\na_"
+        );
+    }
+
+    #[test]
+    fn test_flexible_whitespace_reindents_replacement() {
+        let build = |find: &str, with: &str| {
+            Replacer::new(
+                find,
+                with,
+                true,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                0,
+                None,
+                None,
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Flexible,
+            )
+        };
+        let vars = TemplateVars::default();
+        let text = b"fn f() {\n    if a {  \n        b();\n    }\n}\n";
+
+        // Dedented and without trailing whitespace, unlike the file
+        let replacer = build("if a {\n  b();\n}\n", "if a {\n  b();\n\n  c();\n}\n").unwrap();
+        let (output, count) = replacer.replace_with_count(text, &vars).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            &output[..],
+            b"fn f() {\n    if a {\n      b();\n\n      c();\n    }\n}\n"
+        );
+
+        let (output, count) = build("if  a\t{ b();", "x")
+            .unwrap()
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!(
+            (&output[..], count),
+            (&b"fn f() {\n    x\n    }\n}\n"[..], 1)
+        );
+
+        let (output, count) = build("if a { c();", "x")
+            .unwrap()
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!(count, 0);
+        assert!(matches!(output, Cow::Borrowed(_)));
+
+        assert!(build(" \n ", "x").is_err());
+    }
+
+    #[test]
+    fn test_flexible_whitespace_keeps_trailing_replacement_text() {
+        let build = |find: &str, with: &str| {
+            Replacer::new(
+                find,
+                with,
+                true,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                false,
+                0,
+                None,
+                None,
+                false,
+                ValidationMode::default(),
+                Engine::default(),
+                Whitespace::Flexible,
+            )
+            .unwrap()
+        };
+        let vars = TemplateVars::default();
+        let text = b"    a();  \n    b();\n";
+
+        // The line break of FIND matches the one in the file, after its trailing spaces
+        let (output, count) = build("a();\n", "c();\nd();\n")
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(&output[..], b"    c();\n    d();\n    b();\n");
+
+        // REPLACE is kept as written, as with exact matching: FIND does not cover the line
+        // break after `b();`, so the one REPLACE ends with is added and leaves a blank line
+        let (output, _) = build("b();", "e();\n")
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!(&output[..], b"    a();  \n    e();\n\n");
+
+        let (output, _) = build("a();", "f(); ")
+            .replace_with_count(text, &vars)
+            .unwrap();
+        assert_eq!(&output[..], b"    f();   \n    b();\n");
+    }
 }
@@ -0,0 +1,466 @@
+use super::case::{capitalize, Convention};
+use crate::eol;
+use crate::model::LineEnding;
+use std::borrow::Cow;
+use std::path::Path;
+
+/// A case or naming-convention transform, as in `${1:upper}` or `${name:snake}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    Upper,
+    Lower,
+    /// First letter uppercase, the rest lowercase.
+    Title,
+    Convention(Convention),
+}
+
+impl Transform {
+    /// All transform names, for error messages.
+    pub const NAMES: &'static str = "upper, lower, title, snake, screaming, kebab, camel, pascal";
+
+    pub fn parse(name: &str) -> Option<Self> {
+        let transform = match name {
+            "upper" => Transform::Upper,
+            "lower" => Transform::Lower,
+            "title" => Transform::Title,
+            "snake" => Transform::Convention(Convention::Snake),
+            "screaming" => Transform::Convention(Convention::Screaming),
+            "kebab" => Transform::Convention(Convention::Kebab),
+            "camel" => Transform::Convention(Convention::Camel),
+            "pascal" => Transform::Convention(Convention::Pascal),
+            _ => return None,
+        };
+        Some(transform)
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            Transform::Upper => text.to_uppercase(),
+            Transform::Lower => text.to_lowercase(),
+            Transform::Title => capitalize(&text.to_lowercase()),
+            Transform::Convention(convention) => convention.convert(text),
+        }
+    }
+
+    /// Apply to raw bytes. Bytes that are not valid UTF-8 are left unchanged.
+    fn apply_bytes(self, bytes: &[u8], out: &mut Vec<u8>) {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => out.extend_from_slice(self.apply(text).as_bytes()),
+            Err(_) => out.extend_from_slice(bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Group {
+    Index(usize),
+    Name(String),
+}
+
+/// A reserved variable, as in `${__file}` or `${__n}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Var {
+    /// The input path as given.
+    File,
+    /// The last component of the input path.
+    Basename,
+    /// 1-based line of the match.
+    Line,
+    /// 1-based byte column of the match.
+    Col,
+    /// 1-based ordinal of the match among this operation's replacements in the input.
+    N,
+    /// 1-based ordinal of the match among this operation's replacements in the run.
+    Global,
+}
+
+impl Var {
+    /// All variable names, for error messages.
+    pub const NAMES: &'static str = "__file, __basename, __line, __col, __n, __global";
+
+    /// Parse a reserved name. Names that do not start with `__` are capture groups.
+    pub fn parse(name: &str) -> Option<Self> {
+        let var = match name {
+            "__file" => Var::File,
+            "__basename" => Var::Basename,
+            "__line" => Var::Line,
+            "__col" => Var::Col,
+            "__n" => Var::N,
+            "__global" => Var::Global,
+            _ => return None,
+        };
+        Some(var)
+    }
+}
+
+/// Where a replacement happens, for the reserved variables.
+///
+/// Offsets let a caller that expands part of an input (a line, when streaming)
+/// continue the numbering of what came before.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TemplateVars<'a> {
+    pub file: &'a str,
+    /// Lines before the text being replaced.
+    pub line_offset: usize,
+    /// Replacements already made in this input.
+    pub n_offset: usize,
+    /// Replacements already made in this run.
+    pub global_offset: usize,
+}
+
+/// The variables of one match.
+pub struct MatchVars<'a> {
+    pub vars: &'a TemplateVars<'a>,
+    /// 1-based line and byte column, if the template needs them.
+    pub position: (usize, usize),
+    /// Replacements before this one in the text being replaced.
+    pub index: usize,
+}
+
+impl MatchVars<'_> {
+    fn write(&self, var: Var, out: &mut Vec<u8>) {
+        let vars = self.vars;
+        let number = match var {
+            Var::File => {
+                out.extend_from_slice(vars.file.as_bytes());
+                return;
+            }
+            Var::Basename => {
+                let name = Path::new(vars.file).file_name().unwrap_or_default();
+                out.extend_from_slice(name.to_string_lossy().as_bytes());
+                return;
+            }
+            Var::Line => vars.line_offset + self.position.0,
+            Var::Col => self.position.1,
+            Var::N => vars.n_offset + self.index + 1,
+            Var::Global => vars.global_offset + self.index + 1,
+        };
+        out.extend_from_slice(number.to_string().as_bytes());
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(Vec<u8>),
+    Group {
+        group: Group,
+        transform: Option<Transform>,
+    },
+    Var {
+        var: Var,
+        transform: Option<Transform>,
+    },
+    /// `\U` or `\L` (`Some`) and `\E` (`None`): case applied to everything that follows.
+    Case(Option<Transform>),
+}
+
+/// The capture groups of one match, from either regex engine.
+pub trait Groups {
+    fn index(&self, index: usize) -> Option<&[u8]>;
+    fn name(&self, name: &str) -> Option<&[u8]>;
+}
+
+impl Groups for regex::bytes::Captures<'_> {
+    fn index(&self, index: usize) -> Option<&[u8]> {
+        self.get(index).map(|m| m.as_bytes())
+    }
+
+    fn name(&self, name: &str) -> Option<&[u8]> {
+        regex::bytes::Captures::name(self, name).map(|m| m.as_bytes())
+    }
+}
+
+#[cfg(feature = "fancy")]
+impl Groups for fancy_regex::Captures<'_> {
+    fn index(&self, index: usize) -> Option<&[u8]> {
+        self.get(index).map(|m| m.as_str().as_bytes())
+    }
+
+    fn name(&self, name: &str) -> Option<&[u8]> {
+        fancy_regex::Captures::name(self, name).map(|m| m.as_str().as_bytes())
+    }
+}
+
+/// A replacement with capture groups, expanded per match.
+///
+/// Supports everything `Captures::expand` does (`$1`, `$name`, `${name}`, `$$`), plus
+/// `${group:transform}`, sed-style `\U` / `\L` up to `\E` and the `Var` names.
+/// `\\` is a literal backslash, so `\\U` is not a case change.
+/// Unknown transform names are ignored here; `validate_replacement` reports them.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(replacement: &str) -> Self {
+        let bytes = replacement.as_bytes();
+        let mut segments = Vec::new();
+        let mut literal = Vec::new();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match (bytes[i], bytes.get(i + 1)) {
+                (b'\\', Some(b'\\')) => {
+                    literal.push(b'\\');
+                    i += 2;
+                }
+                (b'\\', Some(&mode @ (b'U' | b'L' | b'E'))) => {
+                    flush(&mut literal, &mut segments);
+                    segments.push(Segment::Case(match mode {
+                        b'U' => Some(Transform::Upper),
+                        b'L' => Some(Transform::Lower),
+                        _ => None,
+                    }));
+                    i += 2;
+                }
+                (b'$', Some(b'$')) => {
+                    literal.push(b'$');
+                    i += 2;
+                }
+                (b'$', Some(b'{')) => match replacement[i + 2..].find('}') {
+                    Some(len) => {
+                        let inner = &replacement[i + 2..i + 2 + len];
+                        let (name, transform) = match inner.split_once(':') {
+                            Some((name, transform)) => (name, Transform::parse(transform)),
+                            None => (inner, None),
+                        };
+                        flush(&mut literal, &mut segments);
+                        segments.push(segment(name, transform));
+                        i += 3 + len;
+                    }
+                    None => {
+                        literal.push(b'$');
+                        i += 1;
+                    }
+                },
+                (b'$', Some(_)) => {
+                    let len = bytes[i + 1..]
+                        .iter()
+                        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                        .count();
+                    if len == 0 {
+                        literal.push(b'$');
+                        i += 1;
+                    } else {
+                        flush(&mut literal, &mut segments);
+                        segments.push(segment(&replacement[i + 1..i + 1 + len], None));
+                        i += 1 + len;
+                    }
+                }
+                (b, _) => {
+                    literal.push(b);
+                    i += 1;
+                }
+            }
+        }
+        flush(&mut literal, &mut segments);
+
+        Self { segments }
+    }
+
+    /// The expansion, if it is the same for every match (no groups, variables or case changes).
+    pub fn as_literal(&self) -> Option<Vec<u8>> {
+        let mut text = Vec::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(bytes) => text.extend_from_slice(bytes),
+                _ => return None,
+            }
+        }
+        Some(text)
+    }
+
+    /// Whether expanding needs the line and column of each match.
+    pub fn uses_position(&self) -> bool {
+        self.segments.iter().any(|segment| {
+            matches!(
+                segment,
+                Segment::Var {
+                    var: Var::Line | Var::Col,
+                    ..
+                }
+            )
+        })
+    }
+
+    /// Whether the template numbers matches across the whole run.
+    #[cfg(any(feature = "parallel", test))]
+    pub fn uses_global(&self) -> bool {
+        self.segments.iter().any(|segment| {
+            matches!(
+                segment,
+                Segment::Var {
+                    var: Var::Global,
+                    ..
+                }
+            )
+        })
+    }
+
+    /// Rewrite the newlines of the literal text to `eol`.
+    pub fn with_eol(mut self, eol: LineEnding) -> Self {
+        for segment in &mut self.segments {
+            if let Segment::Literal(bytes) = segment {
+                if let (Cow::Owned(normalized), _) = eol::normalize(bytes, eol) {
+                    *bytes = normalized;
+                }
+            }
+        }
+        self
+    }
+
+    /// Append the replacement for one match to `out`.
+    pub fn expand(&self, caps: &impl Groups, vars: &MatchVars, out: &mut Vec<u8>) {
+        let mut case = None;
+        let mut piece = Vec::new();
+        for segment in &self.segments {
+            piece.clear();
+            match segment {
+                Segment::Case(mode) => {
+                    case = *mode;
+                    continue;
+                }
+                Segment::Literal(bytes) => piece.extend_from_slice(bytes),
+                Segment::Group { group, transform } => {
+                    let text = match group {
+                        Group::Index(index) => caps.index(*index),
+                        Group::Name(name) => caps.name(name),
+                    };
+                    let text = text.unwrap_or_default();
+                    match transform {
+                        Some(transform) => transform.apply_bytes(text, &mut piece),
+                        None => piece.extend_from_slice(text),
+                    }
+                }
+                Segment::Var { var, transform } => match transform {
+                    Some(transform) => {
+                        let mut text = Vec::new();
+                        vars.write(*var, &mut text);
+                        transform.apply_bytes(&text, &mut piece);
+                    }
+                    None => vars.write(*var, &mut piece),
+                },
+            }
+            match case {
+                Some(transform) => transform.apply_bytes(&piece, out),
+                None => out.extend_from_slice(&piece),
+            }
+        }
+    }
+}
+
+fn flush(literal: &mut Vec<u8>, segments: &mut Vec<Segment>) {
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(std::mem::take(literal)));
+    }
+}
+
+fn segment(name: &str, transform: Option<Transform>) -> Segment {
+    match Var::parse(name) {
+        Some(var) => Segment::Var { var, transform },
+        None => Segment::Group {
+            group: group(name),
+            transform,
+        },
+    }
+}
+
+/// Group references follow `Captures::expand`: all digits is an index, anything else a name.
+fn group(name: &str) -> Group {
+    match name.parse() {
+        Ok(index) => Group::Index(index),
+        Err(_) => Group::Name(name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::bytes::Regex;
+
+    fn expand(pattern: &str, replacement: &str, text: &str) -> String {
+        let vars = TemplateVars::default();
+        expand_at(pattern, replacement, text, &vars, 0)
+    }
+
+    fn expand_at(
+        pattern: &str,
+        replacement: &str,
+        text: &str,
+        vars: &TemplateVars,
+        index: usize,
+    ) -> String {
+        let re = Regex::new(pattern).unwrap();
+        let caps = re.captures(text.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        let vars = MatchVars {
+            vars,
+            position: (2, 5),
+            index,
+        };
+        Template::parse(replacement).expand(&caps, &vars, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn literal_templates() {
+        assert_eq!(
+            Template::parse("a $$ \\n").as_literal().unwrap(),
+            b"a $ \\n"
+        );
+        for replacement in ["$1", "${name}", "${__n}", "\\Ux"] {
+            assert!(Template::parse(replacement).as_literal().is_none());
+        }
+    }
+
+    #[test]
+    fn group_transforms() {
+        let re = r"(?P<first>\w+) (\w+)";
+        assert_eq!(
+            expand(re, "${1:upper}-${2:title}", "hello wORLD"),
+            "HELLO-World"
+        );
+        assert_eq!(expand(re, "${first:snake}", "fooBar x"), "foo_bar");
+        assert_eq!(expand(re, "${first:pascal}$$", "user_id x"), "UserId$");
+        assert_eq!(expand(re, "${1:screaming}/${2:kebab}", "a_b cD"), "A_B/c-d");
+    }
+
+    #[test]
+    fn sed_style_case_spans() {
+        let re = r"(\w+) (\w+)";
+        assert_eq!(expand(re, r"\U$1\E $2", "abc def"), "ABC def");
+        assert_eq!(expand(re, r"\L$1 and $2", "ABC DEF"), "abc and def");
+        assert_eq!(expand(re, r"\U${2:snake}", "x fooBar"), "FOO_BAR");
+        // `\\` escapes a backslash, e.g. in a Windows path
+        assert_eq!(expand(re, r"C:\\Users\\$2", "x alice"), r"C:\Users\alice");
+    }
+
+    #[test]
+    fn reserved_variables() {
+        let vars = TemplateVars {
+            file: "src/Main.rs",
+            line_offset: 10,
+            n_offset: 3,
+            global_offset: 7,
+        };
+        assert_eq!(
+            expand_at(
+                "x",
+                "${__basename}:$__line:${__col} $__n/${__global}",
+                "x",
+                &vars,
+                1
+            ),
+            "Main.rs:12:5 5/9"
+        );
+        assert_eq!(
+            expand_at("x", "${__file:upper} ${__basename:snake}", "x", &vars, 0),
+            "SRC/MAIN.RS main.rs"
+        );
+        let template = Template::parse("id_${__global}");
+        assert!(template.uses_global() && !template.uses_position());
+        // Other names starting with `_` are still capture groups
+        assert_eq!(expand(r"(?P<_x>\w)", "${_x}", "q"), "q");
+    }
+}
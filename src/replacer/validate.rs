@@ -1,11 +1,13 @@
+use super::template::{Transform, Var};
 use crate::error::{Error, Result};
 use crate::model::ValidationMode;
 use std::borrow::Cow;
 use std::str::CharIndices;
 
 /// Validate replacement string for valid capture group references.
-/// Checks for $0, $1, $2, ..., ${1}, ${name}.
-/// Detects ambiguous forms like $1bad (should be ${1}bad).
+/// Checks for $0, $1, $2, ..., ${1}, ${name}, transforms such as ${1:upper} and reserved
+/// variables such as ${__file}. Detects ambiguous forms like $1bad (should be ${1}bad),
+/// unknown transform names and unknown reserved names.
 pub fn validate_replacement(replacement: &str, mode: ValidationMode) -> Result<Cow<'_, str>> {
     if mode == ValidationMode::None {
         return Ok(Cow::Borrowed(replacement));
@@ -19,9 +21,18 @@ pub fn validate_replacement(replacement: &str, mode: ValidationMode) -> Result<C
         let name = capture.name;
         // Handle braced references: ${...}
         if name.starts_with('{') && name.ends_with('}') {
-            // Braced is unambiguous
+            // Braced is unambiguous, but a transform or reserved name must be known
+            let inner = &name[1..name.len() - 1];
+            match inner.split_once(':') {
+                Some((group, transform)) => {
+                    check_var(group, mode)?;
+                    check_transform(group, transform, mode)?;
+                }
+                None => check_var(inner, mode)?,
+            }
             continue;
         }
+        check_var(name, mode)?;
 
         // Unbraced reference: $name
         // Check if name starts with digit and has trailing non-digit characters
@@ -86,6 +97,50 @@ pub fn validate_replacement(replacement: &str, mode: ValidationMode) -> Result<C
     }
 }
 
+/// Reject (strict) or warn about (warn) an unknown transform in `${group:transform}`.
+/// Unknown transforms leave the group unchanged.
+fn check_transform(group: &str, transform: &str, mode: ValidationMode) -> Result<()> {
+    if Transform::parse(transform).is_some() {
+        return Ok(());
+    }
+    let message = format!(
+        "Unknown transform `{}` in `${{{}:{}}}` (expected one of: {})",
+        transform,
+        group,
+        transform,
+        Transform::NAMES
+    );
+    match mode {
+        ValidationMode::Strict => Err(Error::Validation(message)),
+        ValidationMode::Warn => {
+            eprintln!("WARN: {}; the group is inserted unchanged.", message);
+            Ok(())
+        }
+        ValidationMode::None => Ok(()),
+    }
+}
+
+/// Reject (strict) or warn about (warn) a `__` name that is not a reserved variable.
+/// Such names are looked up as capture groups.
+fn check_var(name: &str, mode: ValidationMode) -> Result<()> {
+    if !name.starts_with("__") || Var::parse(name).is_some() {
+        return Ok(());
+    }
+    let message = format!(
+        "Unknown template variable `{}` (expected one of: {})",
+        name,
+        Var::NAMES
+    );
+    match mode {
+        ValidationMode::Strict => Err(Error::Validation(message)),
+        ValidationMode::Warn => {
+            eprintln!("WARN: {}; it is read as a capture group.", message);
+            Ok(())
+        }
+        ValidationMode::None => Ok(()),
+    }
+}
+
 /// A capture group reference found in the replacement string.
 #[derive(Debug)]
 struct Capture<'a> {
@@ -262,6 +317,29 @@ mod tests {
         assert_eq!(result, "${10}bad");
     }
 
+    #[test]
+    fn test_unknown_transform() {
+        for input in ["${1:upper}", "${name:snake}", "\\U$1\\E"] {
+            assert!(validate_replacement(input, ValidationMode::Strict).is_ok());
+        }
+        let err = validate_replacement("${1:shout}", ValidationMode::Strict).unwrap_err();
+        assert!(err.to_string().contains("Unknown transform `shout`"));
+        let result = validate_replacement("${1:shout}", ValidationMode::Warn).unwrap();
+        assert_eq!(result, "${1:shout}");
+    }
+
+    #[test]
+    fn test_unknown_template_variable() {
+        for input in ["${__file}", "$__n", "${__basename:upper}", "${_x}"] {
+            assert!(validate_replacement(input, ValidationMode::Strict).is_ok());
+        }
+        let err = validate_replacement("id-$__count", ValidationMode::Strict).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unknown template variable `__count`"));
+        assert!(validate_replacement("${__count}", ValidationMode::Warn).is_ok());
+    }
+
     #[test]
     fn test_ambiguous_capture_none() {
         let input = "$1bad";
@@ -1,10 +1,23 @@
-use crate::events::{Event, FileEvent, Policies, RunEnd, RunStart, SkipReason};
-use crate::model::Pipeline;
+use crate::events::{Event, FileEvent, OperationStats, Policies, RunEnd, RunStart, SkipReason};
+use crate::model::{Encoding, Pipeline};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+/// A file-level change performed by a file operation.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Created,
+    Deleted,
+    /// The file was moved here from `from`.
+    Renamed {
+        from: PathBuf,
+    },
+}
+
 /// Result of processing a single file.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FileResult {
     /// Path to the file.
     pub path: PathBuf,
@@ -26,6 +39,36 @@ pub struct FileResult {
     pub generated_content: Option<String>,
     /// Whether this file is virtual (not on disk).
     pub is_virtual: bool,
+    /// Number of ensure operations that were no-ops because the content was already present.
+    pub already_present: usize,
+    /// Replacements per `replace_map` key.
+    pub map_replacements: BTreeMap<String, usize>,
+    /// 1-based line and column of the matches of each operation with an `expect` or
+    /// `expect_per_file`, by operation index.
+    pub expected_matches: BTreeMap<usize, Vec<(usize, usize)>>,
+    /// File-level change (create, delete, rename), if this result comes from a file operation.
+    pub change: Option<FileChange>,
+    /// Encoding the file was read and written in (files on disk only).
+    pub encoding: Option<Encoding>,
+    /// Whether the file has a byte order mark.
+    pub bom: bool,
+    /// Non-fatal problems, such as mixed line endings.
+    pub warnings: Vec<String>,
+    /// What each operation did to this file, in pipeline order.
+    pub operations: Vec<OperationStats>,
+}
+
+impl FileResult {
+    /// One-line human description of a file-level change.
+    fn describe_change(&self, change: &FileChange) -> String {
+        match change {
+            FileChange::Created => format!("{}: created", self.path.display()),
+            FileChange::Deleted => format!("{}: deleted", self.path.display()),
+            FileChange::Renamed { from } => {
+                format!("{}: renamed to {}", from.display(), self.path.display())
+            }
+        }
+    }
 }
 
 /// Overall execution report.
@@ -125,10 +168,18 @@ impl Report {
                 self.total, self.modified, self.replacements
             );
             for file in &self.files {
+                for warning in &file.warnings {
+                    eprintln!("  {}: warning - {}", file.path.display(), warning);
+                }
                 if let Some(err) = &file.error {
                     eprintln!("  {}: ERROR - {}", file.path.display(), err);
                 } else if let Some(reason) = &file.skipped {
                     println!("  {}: skipped ({})", file.path.display(), reason);
+                } else if let Some(change) = &file.change {
+                    println!("  {}", file.describe_change(change));
+                    if let Some(diff) = &file.diff {
+                        println!("{}", diff);
+                    }
                 } else if file.modified {
                     println!(
                         "  {}: modified ({} replacements)",
@@ -184,10 +235,15 @@ impl Report {
                 self.total, self.modified, self.replacements
             );
             for file in &self.files {
+                for warning in &file.warnings {
+                    eprintln!("  {}: warning - {}", file.path.display(), warning);
+                }
                 if let Some(err) = &file.error {
                     eprintln!("  {}: ERROR - {}", file.path.display(), err);
                 } else if let Some(reason) = &file.skipped {
                     println!("  {}: skipped ({})", file.path.display(), reason);
+                } else if let Some(change) = &file.change {
+                    println!("  {}", file.describe_change(change));
                 } else if file.modified {
                     println!(
                         "  {}: modified ({} replacements)",
@@ -244,6 +300,28 @@ impl Report {
         }
     }
 
+    /// Per-operation stats summed over all files.
+    fn operation_totals(&self) -> Vec<OperationStats> {
+        let mut totals: Vec<OperationStats> = Vec::new();
+        for file in &self.files {
+            for op in &file.operations {
+                if totals.len() <= op.index {
+                    totals.resize_with(op.index + 1, Default::default);
+                }
+                let total = &mut totals[op.index];
+                total.index = op.index;
+                total.matches += op.matches;
+                total.changed |= op.changed;
+                total.warnings.extend(
+                    op.warnings
+                        .iter()
+                        .map(|warning| format!("{}: {}", file.path.display(), warning)),
+                );
+            }
+        }
+        totals
+    }
+
     /// Print report as JSON events.
     pub fn print_json(
         &self,
@@ -297,6 +375,20 @@ impl Report {
                     path: file.path.clone(),
                     reason: reason_enum,
                 }
+            } else if let Some(change) = &file.change {
+                match change {
+                    FileChange::Created => FileEvent::Created {
+                        path: file.path.clone(),
+                        diff: file.diff.clone(),
+                    },
+                    FileChange::Deleted => FileEvent::Deleted {
+                        path: file.path.clone(),
+                    },
+                    FileChange::Renamed { from } => FileEvent::Renamed {
+                        from: from.clone(),
+                        to: file.path.clone(),
+                    },
+                }
             } else {
                 FileEvent::Success {
                     path: file.path.clone(),
@@ -306,6 +398,12 @@ impl Report {
                     generated_content: file.generated_content.clone(),
                     diff_is_binary: file.diff_is_binary,
                     is_virtual: file.is_virtual,
+                    already_present: file.already_present,
+                    map_replacements: file.map_replacements.clone(),
+                    encoding: file.encoding.map(|e| e.name().to_string()),
+                    bom: file.bom,
+                    warnings: file.warnings.clone(),
+                    operations: file.operations.clone(),
                 }
             };
             println!("{}", serde_json::to_string(&Event::File(event)).unwrap());
@@ -321,6 +419,7 @@ impl Report {
             committed: self.committed,
             duration_ms: self.duration_ms,
             exit_code: self.exit_code(),
+            operations: self.operation_totals(),
         };
         println!("{}", serde_json::to_string(&Event::RunEnd(end)).unwrap());
     }
@@ -333,6 +432,8 @@ impl Report {
                 println!("ERROR: {}", err);
             } else if let Some(reason) = &file.skipped {
                 println!("SKIPPED: {}", reason);
+            } else if let Some(change) = &file.change {
+                println!("{}", file.describe_change(change));
             } else if let Some(diff) = &file.diff {
                 println!("{}", diff);
             } else if file.modified {
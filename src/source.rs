@@ -0,0 +1,284 @@
+use crate::error::Result;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Files at least this large are memory-mapped instead of read (with the `mapped` feature).
+#[cfg(feature = "mapped")]
+pub const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// The raw bytes of an input file.
+pub enum Source {
+    Read(Vec<u8>),
+    /// A read-only mapping of a large file. Only read it inside `Source::guard`.
+    #[cfg(feature = "mapped")]
+    Mapped {
+        map: memmap2::Mmap,
+        file: std::fs::File,
+    },
+}
+
+impl Source {
+    /// Load `path`. With the `mapped` feature, files of at least `MMAP_THRESHOLD` bytes are mapped.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        #[cfg(feature = "mapped")]
+        {
+            use std::io::Read;
+
+            let mut file = std::fs::File::open(path)?;
+            let len = file.metadata()?.len();
+            if len >= MMAP_THRESHOLD {
+                // SAFETY: the mapping is read-only and only read inside `guard`, which turns
+                // faults caused by a concurrent truncation into an error.
+                let map = unsafe { memmap2::Mmap::map(&file)? };
+                return Ok(Source::Mapped { map, file });
+            }
+            let mut bytes = Vec::with_capacity(len as usize);
+            file.read_to_end(&mut bytes)?;
+            Ok(Source::Read(bytes))
+        }
+        #[cfg(not(feature = "mapped"))]
+        std::fs::read(path).map(Source::Read)
+    }
+
+    /// Run `f`, which may read the source.
+    ///
+    /// If a mapped file shrinks while `f` runs, the missing pages read as zeros and the
+    /// result is discarded with `Error::Truncated` instead of the process dying of SIGBUS.
+    pub fn guard<T>(&self, f: impl FnOnce() -> T) -> Result<T> {
+        match self {
+            Source::Read(_) => Ok(f()),
+            #[cfg(feature = "mapped")]
+            Source::Mapped { map, file } => {
+                let (value, faulted) = guard::run(map.as_ptr() as usize, map.len(), f);
+                let shrunk = file.metadata().map_or(true, |m| m.len() < map.len() as u64);
+                if faulted || shrunk {
+                    return Err(crate::error::Error::Truncated(
+                        "file was truncated while being read".into(),
+                    ));
+                }
+                Ok(value)
+            }
+        }
+    }
+}
+
+impl Deref for Source {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Source::Read(bytes) => bytes,
+            #[cfg(feature = "mapped")]
+            Source::Mapped { map, .. } => map,
+        }
+    }
+}
+
+/// SIGBUS handling for reads of a mapped file that was truncated underneath us.
+///
+/// The handler may interrupt any code, including the allocator, so it only does work that is
+/// safe there: it loads atomics, maps zeros over the faulting page and stores a flag. `mmap`
+/// is not on POSIX's list of async-signal-safe functions, but it is a single system call that
+/// touches no userspace state, which is what the list guards against. Thread locals are avoided
+/// as their first access may allocate. Faults outside a guarded region go to whatever handler
+/// was installed before ours, so other users of SIGBUS keep working.
+#[cfg(all(feature = "mapped", unix))]
+mod guard {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Once, OnceLock};
+
+    /// A mapping being read, `start..end`. A slot is free while `end` is 0, and `start` is
+    /// set last so the handler never sees half a region.
+    struct Region {
+        start: AtomicUsize,
+        end: AtomicUsize,
+        faulted: AtomicBool,
+    }
+
+    impl Region {
+        const fn new() -> Self {
+            Self {
+                start: AtomicUsize::new(0),
+                end: AtomicUsize::new(0),
+                faulted: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// One slot per thread reading a mapping; more threads wait for a free slot.
+    static REGIONS: [Region; 64] = [const { Region::new() }; 64];
+    static INSTALL: Once = Once::new();
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+    /// The SIGBUS action that was in place before ours.
+    static PREVIOUS: OnceLock<libc::sigaction> = OnceLock::new();
+
+    /// Run `f` with faults inside `start..start + len` trapped. Returns whether any occurred.
+    pub fn run<T>(start: usize, len: usize, f: impl FnOnce() -> T) -> (T, bool) {
+        INSTALL.call_once(install);
+        let region = claim(start, start + len);
+        let value = f();
+        let faulted = region.faulted.load(Ordering::Acquire);
+        region.start.store(0, Ordering::Release);
+        region.end.store(0, Ordering::Release);
+        (value, faulted)
+    }
+
+    fn claim(start: usize, end: usize) -> &'static Region {
+        loop {
+            for region in &REGIONS {
+                if region
+                    .end
+                    .compare_exchange(0, end, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    region.faulted.store(false, Ordering::Relaxed);
+                    region.start.store(start, Ordering::Release);
+                    return region;
+                }
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    fn install() {
+        // SAFETY: plain libc calls; the previous action is stored before ours can run.
+        unsafe {
+            PAGE_SIZE.store(
+                libc::sysconf(libc::_SC_PAGESIZE) as usize,
+                Ordering::Relaxed,
+            );
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigbus as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGBUS, std::ptr::null(), &mut previous);
+            let _ = PREVIOUS.set(previous);
+            libc::sigaction(libc::SIGBUS, &action, std::ptr::null_mut());
+        }
+    }
+
+    extern "C" fn on_sigbus(sig: libc::c_int, info: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+        // SAFETY: the kernel passes a valid siginfo_t for SA_SIGINFO handlers.
+        let addr = unsafe { fault_address(info) };
+        let page = PAGE_SIZE.load(Ordering::Relaxed);
+        let region = REGIONS.iter().find(|region| {
+            let start = region.start.load(Ordering::Acquire);
+            start != 0 && addr >= start && addr < region.end.load(Ordering::Acquire)
+        });
+        let Some(region) = region.filter(|_| page != 0) else {
+            // SAFETY: forwards the arguments the kernel gave us.
+            return unsafe { forward(sig, info, ctx) };
+        };
+
+        // Back the vanished page with zeros so the interrupted read can complete
+        let base = addr & !(page - 1);
+        // SAFETY: the page lies in a read-only mapping owned by the guarded `Source`.
+        let mapped = unsafe {
+            libc::mmap(
+                base as *mut libc::c_void,
+                page,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                -1,
+                0,
+            )
+        };
+        if mapped == libc::MAP_FAILED {
+            // SAFETY: as above.
+            return unsafe { forward(sig, info, ctx) };
+        }
+        region.faulted.store(true, Ordering::Release);
+    }
+
+    /// Hand a fault we cannot recover from to the previous action.
+    unsafe fn forward(sig: libc::c_int, info: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+        let Some(previous) = PREVIOUS.get() else {
+            return terminate(sig, None);
+        };
+        match previous.sa_sigaction {
+            // Ignoring a fault would re-run the faulting read forever
+            libc::SIG_DFL | libc::SIG_IGN => terminate(sig, Some(previous)),
+            handler if previous.sa_flags & libc::SA_SIGINFO != 0 => {
+                let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                    std::mem::transmute(handler);
+                handler(sig, info, ctx);
+            }
+            handler => {
+                let handler: extern "C" fn(libc::c_int) = std::mem::transmute(handler);
+                handler(sig);
+            }
+        }
+    }
+
+    /// Restore the previous (default) action and re-raise, so the process dies as it would
+    /// have without us. Disarming the guard no longer matters at that point.
+    unsafe fn terminate(sig: libc::c_int, previous: Option<&libc::sigaction>) {
+        match previous.filter(|p| p.sa_sigaction == libc::SIG_DFL) {
+            Some(previous) => libc::sigaction(sig, previous, std::ptr::null_mut()),
+            None => {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = libc::SIG_DFL;
+                libc::sigaction(sig, &action, std::ptr::null_mut())
+            }
+        };
+        libc::raise(sig);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn fault_address(info: *const libc::siginfo_t) -> usize {
+        (*info).si_addr() as usize
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    unsafe fn fault_address(info: *const libc::siginfo_t) -> usize {
+        (*info).si_addr as usize
+    }
+}
+
+/// Windows does not allow truncating a file while it is mapped, so there is nothing to trap.
+#[cfg(all(feature = "mapped", not(unix)))]
+mod guard {
+    pub fn run<T>(_start: usize, _len: usize, f: impl FnOnce() -> T) -> (T, bool) {
+        (f(), false)
+    }
+}
+
+#[cfg(all(test, feature = "mapped", unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_files_are_read_and_large_files_mapped() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small");
+        let large = dir.path().join("large");
+        std::fs::write(&small, b"abc").unwrap();
+        std::fs::write(&large, vec![b'x'; MMAP_THRESHOLD as usize]).unwrap();
+
+        assert!(matches!(Source::open(&small).unwrap(), Source::Read(_)));
+        let source = Source::open(&large).unwrap();
+        assert!(matches!(source, Source::Mapped { .. }));
+        let count = source.guard(|| source.iter().filter(|&&b| b == b'x').count());
+        assert_eq!(count.unwrap(), MMAP_THRESHOLD as usize);
+    }
+
+    #[test]
+    fn truncation_under_the_mapping_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shrinking");
+        std::fs::write(&path, vec![b'x'; 2 * MMAP_THRESHOLD as usize]).unwrap();
+
+        let source = Source::open(&path).unwrap();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+
+        let err = source.guard(|| source.contains(&0)).unwrap_err();
+        assert_eq!(err.code(), "E_TRUNCATED");
+    }
+}
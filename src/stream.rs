@@ -0,0 +1,526 @@
+use crate::engine::check_utf8;
+use crate::eol::{self, EolStats};
+use crate::error::{Error, Result};
+use crate::events::OperationStats;
+use crate::model::{LineEnding, LineRange, Operation};
+use crate::plan::{Plan, Step};
+use crate::replacer::{split_terminator, TemplateVars};
+use regex_automata::util::syntax;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+/// Check that every operation can be applied one line at a time.
+pub fn check_streamable(operations: &[Operation]) -> Result<()> {
+    for op in operations {
+        let problem = match op {
+            Operation::Replace {
+                occurrence: Some(_),
+                ..
+            }
+            | Operation::Delete {
+                occurrence: Some(_),
+                ..
+            } => Some("occurrence numbers matches across the whole input"),
+            Operation::Replace {
+                expect,
+                expect_per_file,
+                ..
+            }
+            | Operation::Delete {
+                expect,
+                expect_per_file,
+                ..
+            } if expect.is_some() || expect_per_file.is_some() => {
+                Some("expect and expect_per_file locate matches across the whole input")
+            }
+            Operation::Replace {
+                before,
+                after,
+                literal,
+                ..
+            } if [before, after]
+                .into_iter()
+                .flatten()
+                .any(|context| matches_newline(context, *literal)) =>
+            {
+                Some("before and after must not match line breaks")
+            }
+            Operation::Replace {
+                before,
+                after,
+                literal,
+                multiline,
+                ..
+            } if [before, after]
+                .into_iter()
+                .flatten()
+                .any(|context| anchors_input(context, *literal, *multiline)) =>
+            {
+                Some("before and after must not anchor to the start or end of the input")
+            }
+            Operation::Replace {
+                find,
+                literal,
+                multiline,
+                dot_matches_newline,
+                ..
+            }
+            | Operation::Delete {
+                find,
+                literal,
+                multiline,
+                dot_matches_newline,
+                ..
+            }
+            | Operation::InsertBefore {
+                find,
+                literal,
+                multiline,
+                dot_matches_newline,
+                ..
+            }
+            | Operation::InsertAfter {
+                find,
+                literal,
+                multiline,
+                dot_matches_newline,
+                ..
+            } => {
+                if *dot_matches_newline {
+                    Some("dot_matches_newline lets matches span lines")
+                } else if matches_newline(find, *literal) {
+                    Some("patterns must not match line breaks")
+                } else if anchors_input(find, *literal, *multiline) {
+                    Some("without multiline, ^ and $ anchor to the start and end of the input")
+                } else {
+                    None
+                }
+            }
+            Operation::ReplaceMap { map, .. } => map
+                .keys()
+                .any(|key| key.contains('\n'))
+                .then_some("patterns must not match line breaks"),
+            Operation::ReplaceBlock { .. } => Some("replace_block spans lines"),
+            Operation::EnsureLine { .. } | Operation::EnsureBlock { .. } => {
+                Some("ensure operations need the whole file")
+            }
+            Operation::DeleteLines { .. }
+            | Operation::KeepLines { .. }
+            | Operation::ReplaceLine { .. }
+            | Operation::NormalizeEol { .. } => None,
+        };
+        if let Some(problem) = problem {
+            return Err(Error::Validation(format!(
+                "--stream only supports line-bounded operations ({})",
+                problem
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn matches_newline(pattern: &str, literal: bool) -> bool {
+    pattern.contains('\n') || (!literal && pattern.contains("\\n"))
+}
+
+/// Whether a regex anchors to the start or end of the whole input, which on its own
+/// line would mean the start or end of every line. Unparsable patterns are left to the
+/// regex compiler.
+fn anchors_input(pattern: &str, literal: bool, multiline: bool) -> bool {
+    let config = syntax::Config::new().utf8(false).multi_line(multiline);
+    !literal
+        && syntax::parse_with(pattern, &config)
+            .is_ok_and(|hir| hir.properties().look_set().contains_anchor_haystack())
+}
+
+/// Outcome of streaming one input.
+#[derive(Debug, Default)]
+pub struct StreamOutcome {
+    pub modified: bool,
+    pub replacements: usize,
+    /// Replacements per `replace_map` key.
+    pub map_replacements: BTreeMap<String, usize>,
+    /// Changed lines only, as `-old` / `+new` lines.
+    pub diff: Option<String>,
+    pub warnings: Vec<String>,
+    /// What each operation did, in pipeline order.
+    pub operations: Vec<OperationStats>,
+    /// A NUL byte was found. Streaming stops there and the output is incomplete.
+    pub binary: bool,
+}
+
+/// Apply `plan` to `reader` one line at a time, writing the result to `writer`.
+///
+/// Memory use is bounded by the longest line. Each line is matched on its own, without its
+/// line ending, and line ranges and limits count across the whole input as in memory.
+/// Inserted text follows the line ending of the first line. `file` names the input
+/// for the reserved template variables.
+pub fn stream_lines<R: BufRead, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    plan: &Plan,
+    file: &str,
+    collect_diff: bool,
+) -> Result<StreamOutcome> {
+    let mut stream = LineStream {
+        plan,
+        states: vec![StepState::default(); plan.steps.len()],
+        run_counts: (0..plan.steps.len()).map(|i| plan.run_count(i)).collect(),
+        map_replacements: BTreeMap::new(),
+        file,
+        eol: LineEnding::Lf,
+    };
+    let mut outcome = StreamOutcome::default();
+    let mut diff = String::new();
+    let mut stats = EolStats::default();
+    let mut line = Vec::new();
+    let mut output = Vec::new();
+    let mut first = true;
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if line.contains(&0) {
+            outcome.binary = true;
+            return Ok(outcome);
+        }
+        if first {
+            if line.ends_with(b"\r\n") {
+                stream.eol = LineEnding::Crlf;
+            }
+            first = false;
+        }
+
+        output.clear();
+        stream.run(0, &line, &mut output)?;
+
+        let line_stats = EolStats::count(&output);
+        stats.crlf += line_stats.crlf;
+        stats.lf += line_stats.lf;
+        if output != line {
+            outcome.modified = true;
+            if collect_diff {
+                push_diff(&mut diff, '-', &line);
+                for piece in output.split_inclusive(|&b| b == b'\n') {
+                    push_diff(&mut diff, '+', piece);
+                }
+            }
+        }
+        writer.write_all(&output)?;
+    }
+
+    for (index, (step, state)) in plan.steps.iter().zip(&stream.states).enumerate() {
+        if matches!(step, Step::Replace { .. }) {
+            plan.add_run_count(index, state.count);
+        }
+    }
+    outcome.replacements = stream.states.iter().map(|s| s.count).sum();
+    outcome.operations = plan
+        .steps
+        .iter()
+        .zip(&stream.states)
+        .enumerate()
+        .map(|(index, (step, state))| step.stats(index, state.count, state.changed))
+        .collect();
+    outcome.map_replacements = stream.map_replacements;
+    outcome.warnings.extend(stats.mixed_warning());
+    if outcome.modified && collect_diff {
+        outcome.diff = Some(diff);
+    }
+    Ok(outcome)
+}
+
+/// How far one step has got through its input.
+#[derive(Debug, Default, Clone, Copy)]
+struct StepState {
+    /// Number of lines seen, i.e. the current line number.
+    line: usize,
+    /// Replacements (or affected lines) so far.
+    count: usize,
+    /// Whether the step has changed any line.
+    changed: bool,
+}
+
+struct LineStream<'p> {
+    plan: &'p Plan,
+    states: Vec<StepState>,
+    /// `Plan::run_count` of each step before this input.
+    run_counts: Vec<usize>,
+    map_replacements: BTreeMap<String, usize>,
+    file: &'p str,
+    eol: LineEnding,
+}
+
+impl LineStream<'_> {
+    /// Pass `line` through the steps from `depth` on and append the result to `out`.
+    /// A step may turn a line into several, each of which the next step sees separately.
+    fn run(&mut self, depth: usize, line: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        let Some(step) = self.plan.steps.get(depth) else {
+            out.extend_from_slice(line);
+            return Ok(());
+        };
+        let state = &mut self.states[depth];
+        state.line += 1;
+        let vars = TemplateVars {
+            file: self.file,
+            line_offset: state.line - 1,
+            n_offset: state.count,
+            global_offset: self.run_counts[depth] + state.count,
+        };
+
+        match apply_step(
+            step,
+            state,
+            &mut self.map_replacements,
+            line,
+            self.eol,
+            &vars,
+        )? {
+            None => self.run(depth + 1, line, out),
+            Some(edited) => {
+                self.states[depth].changed |= edited != line;
+                for piece in edited.split_inclusive(|&b| b == b'\n') {
+                    self.run(depth + 1, piece, out)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Apply one step to one line. Returns `None` if the line is unchanged.
+fn apply_step(
+    step: &Step,
+    state: &mut StepState,
+    map_replacements: &mut BTreeMap<String, usize>,
+    line: &[u8],
+    eol: LineEnding,
+    vars: &TemplateVars,
+) -> Result<Option<Vec<u8>>> {
+    let edited = match step {
+        Step::Replace { replacer, name, .. } => {
+            let replacer = replacer.get(eol);
+            let Some(remaining) = remaining(replacer.range(), replacer.limit(), state) else {
+                return Ok(None);
+            };
+            let (content, terminator) = split_terminator(line);
+            let (replaced, n) = replacer.replace_in_line(content, remaining, vars)?;
+            if n == 0 {
+                return Ok(None);
+            }
+            state.count += n;
+            let mut edited = replaced.into_owned();
+            edited.extend_from_slice(terminator);
+            check_utf8(line, edited, name)?
+        }
+        Step::Lines { replacer, action } => {
+            let replacer = replacer.get(eol);
+            if remaining(replacer.range(), replacer.limit(), state).is_none() {
+                return Ok(None);
+            }
+            let Some(edited) = replacer.apply_to_line(line, *action) else {
+                return Ok(None);
+            };
+            state.count += 1;
+            check_utf8(line, edited, "line edit")?
+        }
+        Step::Map { replacer, name } => {
+            let replacer = replacer.get(eol);
+            let Some(remaining) = remaining(replacer.range(), replacer.limit(), state) else {
+                return Ok(None);
+            };
+            let (content, terminator) = split_terminator(line);
+            let (replaced, counts) = replacer.replace_in_line(content, remaining);
+            let n = replacer.tally(counts, map_replacements);
+            if n == 0 {
+                return Ok(None);
+            }
+            state.count += n;
+            let mut edited = replaced.into_owned();
+            edited.extend_from_slice(terminator);
+            check_utf8(line, edited, name)?
+        }
+        Step::NormalizeEol(target) => match eol::normalize(line, *target) {
+            (Cow::Owned(edited), n) => {
+                state.count += n;
+                edited
+            }
+            (Cow::Borrowed(_), _) => return Ok(None),
+        },
+        Step::Block(_) | Step::Ensure(_) => {
+            unreachable!("rejected by check_streamable")
+        }
+    };
+    Ok(Some(edited))
+}
+
+/// The replacements still allowed for the current line (0 = unlimited),
+/// or `None` if the step must leave it alone.
+fn remaining(range: Option<&LineRange>, limit: usize, state: &StepState) -> Option<usize> {
+    if let Some(range) = range {
+        if state.line < range.start || range.end.is_some_and(|end| state.line > end) {
+            return None;
+        }
+    }
+    match limit {
+        0 => Some(0),
+        limit if state.count < limit => Some(limit - state.count),
+        _ => None,
+    }
+}
+
+fn push_diff(diff: &mut String, sign: char, line: &[u8]) {
+    diff.push(sign);
+    diff.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        diff.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{RegexEngine, ValidationMode, Whitespace};
+
+    fn replace(find: &str, with: &str, limit: usize, range: Option<LineRange>) -> Operation {
+        Operation::Replace {
+            find: find.into(),
+            with: with.into(),
+            literal: true,
+            ignore_case: false,
+            smart_case: false,
+            word: false,
+            multiline: false,
+            dot_matches_newline: false,
+            no_unicode: false,
+            limit,
+            range,
+            occurrence: None,
+            before: None,
+            after: None,
+            engine: RegexEngine::default(),
+            backtrack_limit: None,
+            whitespace: Whitespace::default(),
+            expect: None,
+            expect_per_file: None,
+            expand: false,
+            validation_mode: ValidationMode::default(),
+            preserve_case: false,
+        }
+    }
+
+    fn stream(ops: &[Operation], input: &[u8]) -> (Vec<u8>, StreamOutcome) {
+        check_streamable(ops).unwrap();
+        let plan = Plan::compile(ops).unwrap();
+        let mut out = Vec::new();
+        let outcome = stream_lines(input, &mut out, &plan, "test.txt", true).unwrap();
+        (out, outcome)
+    }
+
+    #[test]
+    fn streams_with_ranges_and_limits() {
+        let range = Some(LineRange {
+            start: 2,
+            end: None,
+        });
+        let (out, outcome) = stream(&[replace("x", "y", 2, range)], b"x\nx x\nx\nx\n");
+        assert_eq!(out, b"x\ny y\nx\nx\n");
+        assert_eq!(outcome.replacements, 2);
+        assert!(outcome.modified);
+        assert_eq!(outcome.diff.unwrap(), "-x x\n+y y\n");
+    }
+
+    #[test]
+    fn later_steps_see_split_lines() {
+        let ops = [
+            replace("a", "a\nb", 0, None),
+            Operation::DeleteLines {
+                find: "b".into(),
+                literal: true,
+                ignore_case: false,
+                smart_case: false,
+                word: false,
+                no_unicode: false,
+                limit: 0,
+                range: Some(LineRange {
+                    start: 2,
+                    end: Some(2),
+                }),
+            },
+        ];
+        let (out, outcome) = stream(&ops, b"a\r\nc\r\n");
+        // The inserted line ending follows the first line; line 2 is the inserted "b"
+        assert_eq!(out, b"a\r\nc\r\n");
+        assert_eq!(outcome.replacements, 2);
+        assert!(!outcome.modified);
+    }
+
+    #[test]
+    fn replace_map_counts_per_key() {
+        let map = [("foo", "bar"), ("bar", "foo")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let ops = [Operation::ReplaceMap {
+            map,
+            ignore_case: false,
+            limit: 3,
+            range: None,
+        }];
+        let (out, outcome) = stream(&ops, b"foo bar\nbar bar\n");
+        assert_eq!(out, b"bar foo\nfoo bar\n");
+        assert_eq!(outcome.replacements, 3);
+        assert_eq!(outcome.map_replacements["bar"], 2);
+        assert_eq!(outcome.map_replacements["foo"], 1);
+    }
+
+    #[test]
+    fn stops_at_binary_content() {
+        let (_, outcome) = stream(&[replace("x", "y", 0, None)], b"x\n\0\n");
+        assert!(outcome.binary);
+    }
+
+    #[test]
+    fn rejects_multi_line_operations() {
+        let mut op = replace("x", "y", 0, None);
+        if let Operation::Replace {
+            dot_matches_newline,
+            ..
+        } = &mut op
+        {
+            *dot_matches_newline = true;
+        }
+        assert!(check_streamable(&[op]).is_err());
+        assert!(check_streamable(&[replace("a\nb", "", 0, None)]).is_err());
+
+        // `^` and `$` anchor to the input unless multiline makes them line anchors
+        let anchored = |find: &str, literal: bool, multiline: bool| {
+            let mut op = replace(find, "", 0, None);
+            if let Operation::Replace {
+                literal: l,
+                multiline: m,
+                ..
+            } = &mut op
+            {
+                (*l, *m) = (literal, multiline);
+            }
+            check_streamable(&[op])
+        };
+        assert!(anchored("^a", false, false).is_err());
+        assert!(anchored(r"a\z", false, true).is_err());
+        assert!(anchored("^a$", false, true).is_ok());
+        assert!(anchored("[^a]", false, false).is_ok());
+        assert!(anchored("^a", true, false).is_ok());
+        assert!(check_streamable(&[Operation::EnsureLine {
+            line: "x".into(),
+            position: Default::default(),
+            anchor: None,
+            literal: true,
+            ignore_case: false,
+        }])
+        .is_err());
+    }
+}
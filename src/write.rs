@@ -11,24 +11,91 @@ pub struct WriteOptions {
     pub permissions: PermissionsMode,
 }
 
-/// A staged file write, ready to be committed.
-pub struct StagedEntry {
-    temp: NamedTempFile,
-    target: PathBuf,
+/// A staged filesystem change, ready to be committed.
+pub enum StagedEntry {
+    /// Replace `target` with the content of a temp file (atomic rename).
+    Write {
+        temp: NamedTempFile,
+        target: PathBuf,
+    },
+    /// Remove `target`.
+    Remove { target: PathBuf },
+    /// Move `from` to `to`.
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        overwrite: bool,
+    },
+    /// Create `to` from a temp file and remove `from`.
+    Move {
+        temp: NamedTempFile,
+        from: PathBuf,
+        to: PathBuf,
+    },
 }
 
 impl StagedEntry {
-    /// Commit the staged file (atomic rename).
+    /// Commit the staged change.
     pub fn commit(self) -> Result<()> {
-        self.temp
-            .persist(&self.target)
-            .map_err(|e| Error::Io(e.error))?;
+        match self {
+            StagedEntry::Write { temp, target } => {
+                create_parent(&target)?;
+                temp.persist(&target).map_err(|e| Error::Io(e.error))?;
+            }
+            StagedEntry::Remove { target } => fs::remove_file(&target)?,
+            StagedEntry::Rename {
+                from,
+                to,
+                overwrite,
+            } => {
+                // Re-check right before the rename: fs::rename silently replaces the target.
+                if !overwrite && fs::symlink_metadata(&to).is_ok() {
+                    return Err(Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{} already exists", to.display()),
+                    )));
+                }
+                create_parent(&to)?;
+                fs::rename(&from, &to)?;
+            }
+            StagedEntry::Move { temp, from, to } => {
+                create_parent(&to)?;
+                temp.persist_noclobber(&to)
+                    .map_err(|e| Error::Io(e.error))?;
+                fs::remove_file(&from)?;
+            }
+        }
         Ok(())
     }
 }
 
+/// Create the missing parent directories of `path`.
+fn create_parent(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => Ok(fs::create_dir_all(parent)?),
+        _ => Ok(()),
+    }
+}
+
 /// Prepare a file for writing (create temp, write content, copy perms).
 pub fn stage_file(path: &Path, data: &[u8], options: &WriteOptions) -> Result<StagedEntry> {
+    stage_file_with(path, options, |temp| {
+        // Write data
+        if !data.is_empty() {
+            temp.write_all(data)?;
+            temp.flush()?;
+        }
+        Ok(())
+    })
+}
+
+/// Like `stage_file`, but `write` produces the content directly into the temp file.
+/// Used for streaming, so the new content never has to be held in memory.
+pub fn stage_file_with(
+    path: &Path,
+    options: &WriteOptions,
+    write: impl FnOnce(&mut NamedTempFile) -> Result<()>,
+) -> Result<StagedEntry> {
     let target_path = resolve_symlink(path, options)?;
 
     // Write atomically using a temporary file in the same directory
@@ -57,16 +124,85 @@ pub fn stage_file(path: &Path, data: &[u8], options: &WriteOptions) -> Result<St
         }
     }
 
-    // Write data
+    write(&mut temp)?;
+
+    Ok(StagedEntry::Write {
+        temp,
+        target: target_path,
+    })
+}
+
+/// Prepare a file that may not exist yet (create temp, write content, set perms).
+/// Existing files keep their permissions; new files get `0o644` unless a fixed mode is set.
+/// Missing parent directories are only created on commit, so the temp file is staged in
+/// the closest directory that already exists.
+pub fn stage_new_file(path: &Path, data: &[u8], options: &WriteOptions) -> Result<StagedEntry> {
+    if fs::symlink_metadata(path).is_ok() {
+        return stage_file(path, data, options);
+    }
+    Ok(StagedEntry::Write {
+        temp: new_temp_file(path, data, options, None)?,
+        target: path.to_path_buf(),
+    })
+}
+
+/// Prepare moving `from` to a new file `to` with different content (a rename with edits).
+/// The new file keeps the permissions of `from` unless a fixed mode is set.
+pub fn stage_move(
+    from: &Path,
+    to: &Path,
+    data: &[u8],
+    options: &WriteOptions,
+) -> Result<StagedEntry> {
+    Ok(StagedEntry::Move {
+        temp: new_temp_file(to, data, options, Some(from))?,
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    })
+}
+
+/// Write `data` to a temp file for the new file `path`, with the permissions of `like` if any.
+fn new_temp_file(
+    path: &Path,
+    data: &[u8],
+    options: &WriteOptions,
+    like: Option<&Path>,
+) -> Result<NamedTempFile> {
+    let mut parent = match path.parent() {
+        Some(p) if p.as_os_str().is_empty() => Path::new("."),
+        Some(p) => p,
+        None => return Err(Error::InvalidPath(path.to_path_buf())),
+    };
+    while !parent.is_dir() {
+        parent = match parent.parent() {
+            Some(p) if p.as_os_str().is_empty() => Path::new("."),
+            Some(p) => p,
+            None => return Err(Error::InvalidPath(path.to_path_buf())),
+        };
+    }
+    let mut temp = NamedTempFile::new_in(parent)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = match options.permissions {
+            PermissionsMode::Fixed(mode) => mode,
+            PermissionsMode::Preserve => like
+                .and_then(|like| fs::metadata(like).ok())
+                .map_or(0o644, |meta| meta.permissions().mode()),
+        };
+        temp.as_file()
+            .set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = like;
+
     if !data.is_empty() {
         temp.write_all(data)?;
         temp.flush()?;
     }
 
-    Ok(StagedEntry {
-        temp,
-        target: target_path,
-    })
+    Ok(temp)
 }
 
 /// Write data to a file atomically.
@@ -133,6 +133,32 @@ fn test_rg_json() {
     assert_eq!(content, "hello bar world");
 }
 
+#[test]
+fn test_rg_json_preserve_case_only_touches_matches() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("test_rg_case.txt");
+    fs::write(&file_path, "Foo one\nFoo two\n").unwrap();
+
+    let p_json = file_path.to_str().unwrap().replace('\\', "\\\\");
+    // ripgrep only reported the second line
+    let match_event = format!(
+        r#"{{"type":"match","data":{{"path":{{"text":"{}"}},"lines":{{"text":"Foo two\n"}},"line_number":2,"absolute_offset":8,"submatches":[{{"match":{{"text":"Foo"}},"start":0,"end":3}}]}}}}"#,
+        p_json
+    );
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg("foo")
+        .arg("bar")
+        .arg("--preserve-case")
+        .arg("--rg-json")
+        .write_stdin(format!("{match_event}\n"))
+        .assert()
+        .success();
+
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(content, "Foo one\nBar two\n");
+}
+
 #[test]
 fn test_limit_alias() {
     let dir = tempdir().unwrap();
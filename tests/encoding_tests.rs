@@ -0,0 +1,89 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use tempfile::tempdir;
+
+fn utf16le_with_bom(text: &str) -> Vec<u8> {
+    let mut out = vec![0xFF, 0xFE];
+    out.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    out
+}
+
+fn file_event(stdout: &[u8]) -> Value {
+    let stdout = String::from_utf8(stdout.to_vec()).unwrap();
+    let line = stdout.lines().nth(1).unwrap();
+    serde_json::from_str::<Value>(line).unwrap()["file"].clone()
+}
+
+#[test]
+fn test_utf16le_bom_file_is_edited_and_reencoded() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("strings.rc");
+    fs::write(&file, utf16le_with_bom("IDS_TITLE \"Old Name\"\r\n")).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--format=json")
+        .arg("Old Name")
+        .arg("New Name")
+        .arg(file.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let event = file_event(&output.stdout);
+    assert_eq!(event["type"], "success");
+    assert_eq!(event["replacements"], 1);
+    assert_eq!(event["encoding"], "utf-16le");
+    assert_eq!(event["bom"], true);
+
+    assert_eq!(
+        fs::read(&file).unwrap(),
+        utf16le_with_bom("IDS_TITLE \"New Name\"\r\n")
+    );
+}
+
+#[test]
+fn test_declared_latin1_encoding() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("legacy.txt");
+    fs::write(&file, b"caf\xe9\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--format=json")
+        .arg("--encoding")
+        .arg("latin1")
+        .arg("café")
+        .arg("thé")
+        .arg(file.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(file_event(&output.stdout)["encoding"], "latin1");
+    assert_eq!(fs::read(&file).unwrap(), b"th\xe9\n");
+}
+
+#[test]
+fn test_unrepresentable_character_fails() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("legacy.txt");
+    fs::write(&file, b"price: EUR\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--format=json")
+        .arg("--encoding")
+        .arg("latin1")
+        .arg("EUR")
+        .arg("€")
+        .arg(file.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let event = file_event(&output.stdout);
+    assert_eq!(event["type"], "error");
+    assert_eq!(event["code"], "E_ENCODING");
+    assert_eq!(fs::read(&file).unwrap(), b"price: EUR\n");
+}
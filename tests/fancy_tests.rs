@@ -0,0 +1,94 @@
+#![cfg(feature = "fancy")]
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_fancy_engine_lookaround_and_backreferences() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("prices.txt");
+    fs::write(&file, "$10 and 10 EUR, the the end\n").unwrap();
+
+    let manifest = dir.path().join("manifest.json");
+    let json = serde_json::json!({
+        "files": [file.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "replace",
+                "find": r"(?<=\$)\d+",
+                "with": "20",
+                "engine": "fancy"
+            },
+            {
+                "type": "replace",
+                "find": r"\b(\w+) \1\b",
+                "with": "$1",
+                "expand": true,
+                "engine": "fancy"
+            }
+        ]
+    });
+    fs::write(&manifest, json.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "$20 and 10 EUR, the end\n"
+    );
+}
+
+#[test]
+fn test_fancy_engine_from_cli() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("calls.txt");
+    fs::write(&file, "foo(1) foobar(2)\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--regex")
+        .arg("--engine")
+        .arg("fancy")
+        .arg(r"foo(?=\()")
+        .arg("bar")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "bar(1) foobar(2)\n");
+}
+
+#[test]
+fn test_fancy_engine_backtrack_limit() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("input.txt");
+    let original = format!("{}c\n", "a".repeat(30));
+    fs::write(&file, &original).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--format=json")
+        .arg("--regex")
+        .arg("--engine")
+        .arg("fancy")
+        .arg("--backtrack-limit")
+        .arg("1000")
+        .arg(r"(a|aa)+\1b")
+        .arg("x")
+        .arg(file.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let event = serde_json::from_str::<Value>(stdout.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(event["file"]["type"], "error");
+    assert_eq!(event["file"]["code"], "E_BACKTRACK_LIMIT");
+    assert_eq!(fs::read_to_string(&file).unwrap(), original);
+}
@@ -0,0 +1,247 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn run_manifest(manifest_path: &Path, extra: &[&str]) -> (Option<i32>, Vec<Value>) {
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("apply")
+        .args(extra)
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap())
+        .arg("--format=json")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events = stdout
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    (output.status.code(), events)
+}
+
+#[test]
+fn test_file_operations_refactor_in_one_transaction() {
+    let dir = tempdir().unwrap();
+    let lib = dir.path().join("lib.rs");
+    let foo = dir.path().join("foo.rs");
+    let bar = dir.path().join("bar.rs");
+    let obsolete = dir.path().join("obsolete.rs");
+    let created = dir.path().join("new.rs");
+    fs::write(&lib, "mod foo;\n").unwrap();
+    fs::write(&foo, "pub fn foo() {}\n").unwrap();
+    fs::write(&obsolete, "// unused\n").unwrap();
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [lib.to_str().unwrap(), foo.to_str().unwrap()],
+        "operations": [
+            { "type": "replace", "find": "foo", "with": "bar" }
+        ],
+        "file_operations": [
+            { "type": "rename", "from": foo.to_str().unwrap(), "to": bar.to_str().unwrap() },
+            { "type": "delete", "path": obsolete.to_str().unwrap() },
+            { "type": "create", "path": created.to_str().unwrap(), "content": "// new\n" }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let (code, events) = run_manifest(&manifest_path, &[]);
+    assert_eq!(code, Some(0));
+
+    assert_eq!(fs::read_to_string(&lib).unwrap(), "mod bar;\n");
+    assert!(!foo.exists());
+    assert_eq!(fs::read_to_string(&bar).unwrap(), "pub fn bar() {}\n");
+    assert!(!obsolete.exists());
+    assert_eq!(fs::read_to_string(&created).unwrap(), "// new\n");
+
+    assert_eq!(events[3]["file"]["type"], "renamed");
+    assert_eq!(events[3]["file"]["from"], foo.to_str().unwrap());
+    assert_eq!(events[3]["file"]["to"], bar.to_str().unwrap());
+    assert_eq!(events[4]["file"]["type"], "deleted");
+    assert_eq!(events[5]["file"]["type"], "created");
+    assert_eq!(events[6]["run_end"]["total_modified"], 5);
+}
+
+#[test]
+fn test_file_operations_failure_rolls_back_everything() {
+    let dir = tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    let created = dir.path().join("c.txt");
+    fs::write(&a, "foo").unwrap();
+    fs::write(&b, "taken").unwrap();
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [a.to_str().unwrap()],
+        "operations": [
+            { "type": "replace", "find": "foo", "with": "bar" }
+        ],
+        "file_operations": [
+            { "type": "create", "path": created.to_str().unwrap(), "content": "x" },
+            { "type": "rename", "from": a.to_str().unwrap(), "to": b.to_str().unwrap() }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let (code, events) = run_manifest(&manifest_path, &[]);
+    assert_eq!(code, Some(1));
+    assert_eq!(events[3]["file"]["type"], "error");
+    assert_eq!(events[3]["file"]["code"], "E_EXIST");
+
+    assert_eq!(fs::read_to_string(&a).unwrap(), "foo");
+    assert_eq!(fs::read_to_string(&b).unwrap(), "taken");
+    assert!(!created.exists());
+}
+
+#[test]
+fn test_file_operations_same_path_spelled_differently() {
+    let dir = tempdir().unwrap();
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [],
+        "operations": [],
+        "file_operations": [
+            { "type": "create", "path": "./c.txt", "content": "first\n" },
+            { "type": "create", "path": "c.txt", "content": "second\n" }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let output = cargo_bin_cmd!("txed")
+        .current_dir(dir.path())
+        .args(["apply", "--manifest", "manifest.json", "--format=json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events: Vec<Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(events[2]["file"]["code"], "E_EXIST");
+    assert!(!dir.path().join("c.txt").exists());
+}
+
+#[test]
+fn test_file_operations_reject_editing_their_targets() {
+    let dir = tempdir().unwrap();
+    let old = dir.path().join("old.rs");
+    fs::write(&old, "foo\n").unwrap();
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": ["./new.rs"],
+        "operations": [
+            { "type": "replace", "find": "foo", "with": "bar" }
+        ],
+        "file_operations": [
+            { "type": "rename", "from": "old.rs", "to": "new.rs" }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    cargo_bin_cmd!("txed")
+        .current_dir(dir.path())
+        .args(["apply", "--manifest", "manifest.json"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("target of a file operation"));
+    assert_eq!(fs::read_to_string(&old).unwrap(), "foo\n");
+    assert!(!dir.path().join("new.rs").exists());
+}
+
+#[test]
+fn test_file_operations_dry_run_and_chaining() {
+    let dir = tempdir().unwrap();
+    let created = dir.path().join("draft.txt");
+    let moved = dir.path().join("final.txt");
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [],
+        "operations": [],
+        "file_operations": [
+            { "type": "create", "path": created.to_str().unwrap(), "content": "hello\n" },
+            { "type": "rename", "from": created.to_str().unwrap(), "to": moved.to_str().unwrap() }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let (code, events) = run_manifest(&manifest_path, &["--dry-run"]);
+    assert_eq!(code, Some(0));
+    assert_eq!(events[1]["file"]["type"], "created");
+    assert!(events[1]["file"]["diff"]
+        .as_str()
+        .unwrap()
+        .contains("+hello"));
+    assert_eq!(events[2]["file"]["type"], "renamed");
+    assert!(!created.exists());
+    assert!(!moved.exists());
+
+    let (code, _) = run_manifest(&manifest_path, &[]);
+    assert_eq!(code, Some(0));
+    assert!(!created.exists());
+    assert_eq!(fs::read_to_string(&moved).unwrap(), "hello\n");
+}
+
+#[test]
+fn test_file_operations_create_missing_parent_directories() {
+    let dir = tempdir().unwrap();
+    let old = dir.path().join("old.rs");
+    let created = dir.path().join("src/new/mod.rs");
+    let moved = dir.path().join("src/legacy/old.rs");
+    fs::write(&old, "// old\n").unwrap();
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [],
+        "operations": [],
+        "file_operations": [
+            { "type": "create", "path": created.to_str().unwrap(), "content": "// new\n" },
+            { "type": "rename", "from": old.to_str().unwrap(), "to": moved.to_str().unwrap() }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let (code, _) = run_manifest(&manifest_path, &[]);
+    assert_eq!(code, Some(0));
+    assert_eq!(fs::read_to_string(&created).unwrap(), "// new\n");
+    assert_eq!(fs::read_to_string(&moved).unwrap(), "// old\n");
+    assert!(!old.exists());
+}
+
+#[test]
+fn test_file_operations_delete_rejects_directories() {
+    let dir = tempdir().unwrap();
+    let subdir = dir.path().join("subdir");
+    let created = dir.path().join("c.txt");
+    fs::create_dir(&subdir).unwrap();
+
+    let manifest_path = dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [],
+        "operations": [],
+        "file_operations": [
+            { "type": "create", "path": created.to_str().unwrap(), "content": "x" },
+            { "type": "delete", "path": subdir.to_str().unwrap() }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let (code, events) = run_manifest(&manifest_path, &[]);
+    assert_eq!(code, Some(1));
+    assert_eq!(events[2]["file"]["type"], "error");
+    assert!(events[2]["file"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("is a directory"));
+    assert!(subdir.is_dir());
+    assert!(!created.exists());
+}
@@ -164,3 +164,41 @@ fn test_binary_skip_with_other_files() {
     // Text changed
     assert_eq!(fs::read_to_string(&txt_file).unwrap(), "bar");
 }
+
+#[test]
+fn test_non_utf8_bytes_preserved() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("latin1.txt");
+    // "café foo" in Latin-1, followed by a lone continuation byte
+    fs::write(&file, b"caf\xe9 foo \x80\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("foo")
+        .arg("bar")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert_eq!(fs::read(&file).unwrap(), b"caf\xe9 bar \x80\n");
+}
+
+#[test]
+fn test_edit_producing_invalid_utf8_fails() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("utf8.txt");
+    fs::write(&file, "café\n").unwrap();
+
+    // Without Unicode, \xC3 matches only the first byte of "é"
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--regex")
+        .arg("--no-unicode")
+        .arg("--format=json")
+        .arg(r"\xC3")
+        .arg("")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("E_INVALID_UTF8"));
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "café\n");
+}
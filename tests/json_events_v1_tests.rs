@@ -102,3 +102,64 @@ fn test_json_v1_fields_error_code() {
     assert_eq!(file_event["type"], "error");
     assert_eq!(file_event["code"], "E_NOT_FOUND");
 }
+
+#[test]
+fn test_json_v1_fields_operations() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    fs::write(&a, "foo foo bar\n").unwrap();
+    fs::write(&b, "foo\n").unwrap();
+
+    let manifest = dir.path().join("manifest.json");
+    let json = serde_json::json!({
+        "files": [a.to_str().unwrap(), b.to_str().unwrap()],
+        "operations": [
+            {"type": "replace", "find": "foo", "with": "baz", "limit": 1},
+            {"type": "replace", "find": "missing", "with": "x"},
+            {"type": "replace", "find": "bar", "with": "bar"}
+        ]
+    });
+    fs::write(&manifest, json.to_string()).unwrap();
+
+    for stream in [false, true] {
+        let mut args = vec![
+            "apply",
+            "--manifest",
+            manifest.to_str().unwrap(),
+            "--dry-run",
+        ];
+        if stream {
+            args.push("--stream");
+        }
+        let events = run_txed_json(&args);
+
+        let file = &events[1]["file"];
+        assert_eq!(file["path"], a.to_str().unwrap());
+        assert_eq!(
+            file["operations"],
+            serde_json::json!([
+                {
+                    "index": 0,
+                    "matches": 1,
+                    "changed": true,
+                    "warnings": ["reached the limit of 1 replacements"]
+                },
+                {"index": 1, "matches": 0, "changed": false},
+                {"index": 2, "matches": 1, "changed": false}
+            ])
+        );
+
+        let end = &events.last().unwrap()["run_end"];
+        assert_eq!(end["operations"][0]["matches"], 2);
+        assert_eq!(
+            end["operations"][0]["warnings"],
+            serde_json::json!([
+                format!("{}: reached the limit of 1 replacements", a.display()),
+                format!("{}: reached the limit of 1 replacements", b.display())
+            ])
+        );
+        assert_eq!(end["operations"][1]["matches"], 0);
+        assert_eq!(end["operations"][2]["changed"], false);
+    }
+}
@@ -91,3 +91,558 @@ fn test_operation_delete_regex() {
     let content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(content, "remove numbers");
 }
+
+#[test]
+fn test_operation_insert_before_and_after() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("insert.txt");
+    fs::write(&file_path, "# header\nbody\n# marker\n").unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "expect": 2,
+        "operations": [
+            {
+                "type": "insert_after",
+                "find": "# header\n",
+                "text": "generated\n",
+                "literal": true
+            },
+            {
+                "type": "insert_before",
+                "find": "^# marker",
+                "text": "footer\n",
+                "multiline": true
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(content, "# header\ngenerated\nbody\nfooter\n# marker\n");
+}
+
+#[test]
+fn test_operation_line_operations() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("lines.txt");
+    fs::write(&file_path, "a = 1\n# comment\nb = 2\nDEBUG = true\n").unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "delete_lines",
+                "find": "^#",
+                "literal": false
+            },
+            {
+                "type": "replace_line",
+                "find": "DEBUG",
+                "with": "DEBUG = false",
+                "literal": true
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(content, "a = 1\nb = 2\nDEBUG = false\n");
+}
+
+#[test]
+fn test_operation_replace_block() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("block.rs");
+    fs::write(
+        &file_path,
+        "fn a() {}\n// BEGIN GENERATED\nold();\n// END GENERATED\nfn b() {}\n",
+    )
+    .unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "require_match": true,
+        "operations": [
+            {
+                "type": "replace_block",
+                "start": "// BEGIN GENERATED\n",
+                "end": "// END GENERATED",
+                "with": "new();\n",
+                "literal": true
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+
+    cmd.assert().success();
+
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(
+        content,
+        "fn a() {}\n// BEGIN GENERATED\nnew();\n// END GENERATED\nfn b() {}\n"
+    );
+}
+
+#[test]
+fn test_operation_replace_block_unbalanced() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("block.rs");
+    let original = "// BEGIN GENERATED\nold();\n";
+    fs::write(&file_path, original).unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "replace_block",
+                "start": "// BEGIN GENERATED",
+                "end": "// END GENERATED",
+                "with": "",
+                "literal": true
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap())
+        .arg("--format=json")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("E_BLOCK_MARKERS"));
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), original);
+}
+
+#[test]
+fn test_operation_ensure_line_twice_is_noop() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("ensure.rs");
+    fs::write(&file_path, "use std::fs;\n\nfn main() {}\n").unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "ensure_line",
+                "line": "use std::io;",
+                "position": "after",
+                "anchor": "use std::fs;",
+                "literal": true
+            },
+            {
+                "type": "ensure_block",
+                "block": "#[cfg(test)]\nmod tests {}\n"
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+    cmd.assert().success();
+
+    let expected = "use std::fs;\nuse std::io;\n\nfn main() {}\n#[cfg(test)]\nmod tests {}\n";
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), expected);
+
+    // Re-running the manifest must not change anything.
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--fail-on-change")
+        .arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap())
+        .arg("--format=json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let file_event: serde_json::Value =
+        serde_json::from_str(stdout.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(file_event["file"]["modified"], false);
+    assert_eq!(file_event["file"]["already_present"], 2);
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), expected);
+}
+
+#[test]
+fn test_operation_normalize_eol_and_mixed_warning() {
+    let temp_dir = TempDir::new().unwrap();
+    let mixed = temp_dir.path().join("mixed.txt");
+    let crlf = temp_dir.path().join("crlf.txt");
+    fs::write(&mixed, "a\r\nb\nc\r\n").unwrap();
+    fs::write(&crlf, "x\r\ny\r\n").unwrap();
+
+    // A plain replacement keeps CRLF in the inserted text and warns about mixed endings
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--format=json")
+        .arg("y")
+        .arg("y\nz")
+        .arg(mixed.to_str().unwrap())
+        .arg(crlf.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&crlf).unwrap(), "x\r\ny\r\nz\r\n");
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let warnings = &events[1]["file"]["warnings"];
+    assert!(warnings[0].as_str().unwrap().contains("mixed line endings"));
+    assert!(events[2]["file"].get("warnings").is_none());
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [mixed.to_str().unwrap(), crlf.to_str().unwrap()],
+        "operations": [
+            { "type": "normalize_eol", "eol": "lf" }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+    cmd.assert().success();
+
+    assert_eq!(fs::read_to_string(&mixed).unwrap(), "a\nb\nc\n");
+    assert_eq!(fs::read_to_string(&crlf).unwrap(), "x\ny\nz\n");
+}
+
+#[test]
+fn test_operation_replace_map_swaps_and_counts_per_key() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("swap.txt");
+    fs::write(&file_path, "foo bar foobar foo\n").unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "replace_map",
+                "map": { "foo": "bar", "bar": "foo", "foobar": "baz" }
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap())
+        .arg("--format=json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Replaced text is not matched again, and the longest key wins
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "bar foo baz bar\n");
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let file_event: serde_json::Value =
+        serde_json::from_str(stdout.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(file_event["file"]["replacements"], 4);
+    assert_eq!(
+        file_event["file"]["map_replacements"],
+        serde_json::json!({ "foo": 2, "bar": 1, "foobar": 1 })
+    );
+}
+
+#[test]
+fn test_operation_replace_preserve_case() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("ids.rs");
+    fs::write(
+        &file_path,
+        "let user_id = UserId::new(USER_ID);\nfetch(userId, \"user-id\", user_id);\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--format=json")
+        .arg("--preserve-case")
+        .arg("user_id")
+        .arg("account_id")
+        .arg(file_path.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "let account_id = AccountId::new(ACCOUNT_ID);\nfetch(accountId, \"account-id\", account_id);\n"
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let file_event: serde_json::Value =
+        serde_json::from_str(stdout.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(file_event["file"]["replacements"], 6);
+    assert_eq!(
+        file_event["file"]["map_replacements"],
+        serde_json::json!({
+            "user_id": 2,
+            "USER_ID": 1,
+            "user-id": 1,
+            "userId": 1,
+            "UserId": 1
+        })
+    );
+}
+
+#[test]
+fn test_operation_replace_preserve_case_word() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("users.rs");
+    fs::write(
+        &file_path,
+        "let user = User::new(username, USER);
+",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--preserve-case")
+        .arg("-w")
+        .arg("user")
+        .arg("account")
+        .arg(file_path.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "let account = Account::new(username, ACCOUNT);\n"
+    );
+}
+
+#[test]
+fn test_operation_replace_preserve_case_rejects_ignore_case() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("users.rs");
+    fs::write(&file_path, "user\n").unwrap();
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [{
+            "type": "replace",
+            "find": "user",
+            "with": "account",
+            "preserve_case": true,
+            "ignore_case": true
+        }]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap())
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "user\n");
+}
+
+#[test]
+fn test_operation_delete_last_occurrence() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("args.txt");
+    fs::write(&file_path, "call(x, y, z, )\n").unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "delete",
+                "find": ", ",
+                "literal": true,
+                "occurrence": { "start": -1 }
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+    cmd.assert().success();
+
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "call(x, y, z)\n");
+}
+
+#[test]
+fn test_operation_replace_with_context() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("ctx.rs");
+    fs::write(
+        &file_path,
+        "fn process(x: u8) {}\nfn other(x: u8) {}\nlet x = process(x);\n",
+    )
+    .unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "replace",
+                "find": "x",
+                "with": "input",
+                "literal": true,
+                "before": "fn process(",
+                "after": ":"
+            },
+            {
+                "type": "replace",
+                "find": r"\w+",
+                "with": "value",
+                "before": r"process\(",
+                "after": r"\)"
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+    cmd.assert().success();
+
+    // The context is matched but left in place
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "fn process(input: u8) {}\nfn other(x: u8) {}\nlet x = process(value);\n"
+    );
+}
+
+#[test]
+fn test_operation_replace_with_flexible_whitespace() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("flex.py");
+    fs::write(
+        &file_path,
+        "class A:\n    def f(self):  \n        return 1\n",
+    )
+    .unwrap();
+
+    // Dedented and without the trailing spaces found in the file
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [file_path.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "replace",
+                "find": "def f(self):\n    return 1\n",
+                "with": "def f(self):\n    x = 1\n    return x\n",
+                "literal": true,
+                "whitespace": "flexible"
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap());
+    cmd.assert().success();
+
+    // Lines after the first keep their indentation relative to the matched line
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "class A:\n    def f(self):\n        x = 1\n        return x\n"
+    );
+}
+
+#[test]
+fn test_operation_expect_per_file_reports_locations() {
+    let temp_dir = TempDir::new().unwrap();
+    let unique = temp_dir.path().join("unique.rs");
+    let twice = temp_dir.path().join("twice.rs");
+    fs::write(&unique, "let x = old();\n").unwrap();
+    fs::write(&twice, "old();\nlet y = old();\n").unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    let manifest = serde_json::json!({
+        "files": [unique.to_str().unwrap(), twice.to_str().unwrap()],
+        "operations": [
+            {
+                "type": "replace",
+                "find": "old()",
+                "with": "new()",
+                "literal": true,
+                "expect_per_file": 1
+            }
+        ]
+    });
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("apply")
+        .arg("--manifest")
+        .arg(manifest_path.to_str().unwrap())
+        .arg("--format=json")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let run_end: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(
+        run_end["run_end"]["policy_violation"],
+        format!(
+            "operation 0 expected 1 matches per file, found 2 in {0}: {0}:1:1, {0}:2:9",
+            twice.display()
+        )
+    );
+
+    // Nothing is written when the policy fails
+    assert_eq!(fs::read_to_string(&unique).unwrap(), "let x = old();\n");
+    assert_eq!(
+        fs::read_to_string(&twice).unwrap(),
+        "old();\nlet y = old();\n"
+    );
+}
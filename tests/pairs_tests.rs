@@ -0,0 +1,125 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_expressions_apply_in_order() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("lib.rs");
+    fs::write(&file, "fn old_name() { helper(); }\n").unwrap();
+
+    // The second pair sees the output of the first
+    cargo_bin_cmd!("txed")
+        .args(["-e", "old_name=new_name", "-e", "new_name=final_name"])
+        .args(["--expression", "helper=assist"])
+        .arg(&file)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "fn final_name() { assist(); }\n"
+    );
+}
+
+#[test]
+fn test_pair_takes_all_positionals_as_files() {
+    let dir = tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    fs::write(&first, "key=foo\n").unwrap();
+    fs::write(&second, "key=foo\n").unwrap();
+
+    cargo_bin_cmd!("txed")
+        .args(["--pair", "key=foo", "key=bar"])
+        .arg(&first)
+        .arg(&second)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&first).unwrap(), "key=bar\n");
+    assert_eq!(fs::read_to_string(&second).unwrap(), "key=bar\n");
+}
+
+#[test]
+fn test_pairs_file_tsv_and_json() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("names.txt");
+    fs::write(&file, "alpha beta gamma\n").unwrap();
+    fs::write(dir.path().join("renames.tsv"), "alpha\tone\nbeta\ttwo\n").unwrap();
+    fs::write(dir.path().join("renames.json"), r#"{"gamma": "three"}"#).unwrap();
+
+    for table in ["renames.tsv", "renames.json"] {
+        cargo_bin_cmd!("txed")
+            .current_dir(dir.path())
+            .args(["--pairs-file", table, "names.txt"])
+            .assert()
+            .success();
+    }
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "one two three\n");
+}
+
+#[test]
+fn test_expression_pair_and_pairs_file_combine_in_order() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("names.txt");
+    fs::write(&file, "a\n").unwrap();
+    fs::write(dir.path().join("renames.tsv"), "c\td\n").unwrap();
+
+    cargo_bin_cmd!("txed")
+        .current_dir(dir.path())
+        .args([
+            "-e",
+            "a=b",
+            "--pair",
+            "b",
+            "c",
+            "--pairs-file",
+            "renames.tsv",
+        ])
+        .arg("names.txt")
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "d\n");
+}
+
+#[test]
+fn test_expression_and_pair_keep_command_line_order() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("names.txt");
+    fs::write(&file, "a\n").unwrap();
+
+    // `a -> b` runs first, so `b=c` then sees its output
+    cargo_bin_cmd!("txed")
+        .args([
+            "--pair", "a", "b", "-e", "b=c", "--pair", "c", "d", "-e", "a=x",
+        ])
+        .arg(&file)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "d\n");
+}
+
+#[test]
+fn test_empty_pair_find_names_its_source() {
+    cargo_bin_cmd!("txed")
+        .args(["--pair", "", "x", "--stdin-text"])
+        .write_stdin("text")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Empty FIND in \"--pair\""));
+}
+
+#[test]
+fn test_invalid_expression_fails() {
+    cargo_bin_cmd!("txed")
+        .args(["-e", "missing-separator", "--stdin-text"])
+        .write_stdin("text")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected FIND=REPLACE"));
+}
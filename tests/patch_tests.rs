@@ -0,0 +1,252 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn run_patch(dir: &Path, args: &[&str], stdin: Option<&str>) -> (Option<i32>, Vec<Value>) {
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.current_dir(dir).args(args).arg("--format=json");
+    if let Some(input) = stdin {
+        cmd.write_stdin(input);
+    }
+    let output = cmd.output().unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let events = stdout
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    (output.status.code(), events)
+}
+
+const PATCH: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    old();
++    new();
+ }
+diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -1,2 +1,3 @@
+ # Title
++
+ Body
+";
+
+fn setup() -> tempfile::TempDir {
+    let dir = tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(
+        dir.path().join("src/lib.rs"),
+        "// header\nfn main() {\n    old();\n}\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("README.md"), "# Title\nBody\n").unwrap();
+    fs::write(dir.path().join("change.diff"), PATCH).unwrap();
+    dir
+}
+
+#[test]
+fn test_patch_applies_all_files() {
+    let dir = setup();
+
+    let (code, events) = run_patch(dir.path(), &["--patch", "change.diff"], None);
+    assert_eq!(code, Some(0));
+    assert_eq!(events[0]["run_start"]["input_mode"], "patch");
+    assert_eq!(events[3]["run_end"]["total_replacements"], 2);
+
+    // The first hunk is found one line below its recorded position
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+        "// header\nfn main() {\n    new();\n}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        "# Title\n\nBody\n"
+    );
+}
+
+#[test]
+fn test_patch_rejected_hunk_aborts_transaction() {
+    let dir = setup();
+    fs::write(dir.path().join("README.md"), "# Other\nBody\n").unwrap();
+
+    let (code, events) = run_patch(dir.path(), &["--patch", "change.diff"], None);
+    assert_eq!(code, Some(1));
+
+    let errors: Vec<&Value> = events
+        .iter()
+        .filter(|e| e["file"]["type"] == "error")
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["file"]["code"], "E_HUNK_REJECTED");
+    assert_eq!(errors[0]["file"]["path"], "README.md");
+    assert!(errors[0]["file"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("hunk #1"));
+
+    // Nothing is written, including the file whose hunks did apply
+    assert!(fs::read_to_string(dir.path().join("src/lib.rs"))
+        .unwrap()
+        .contains("old();"));
+}
+
+#[test]
+fn test_patch_fuzz_ignores_outer_context() {
+    let dir = setup();
+    fs::write(dir.path().join("README.md"), "# Renamed\nBody\n").unwrap();
+
+    let (code, _) = run_patch(dir.path(), &["--patch", "change.diff"], None);
+    assert_eq!(code, Some(1));
+
+    let (code, _) = run_patch(dir.path(), &["--patch", "change.diff", "--fuzz", "1"], None);
+    assert_eq!(code, Some(0));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        "# Renamed\n\nBody\n"
+    );
+}
+
+#[test]
+fn test_patch_from_stdin_creates_and_deletes() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("old.txt"), "bye\n").unwrap();
+    let patch = "\
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1,2 @@
++hello
++world
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+";
+
+    let (code, events) = run_patch(dir.path(), &["--patch", "-", "--dry-run"], Some(patch));
+    assert_eq!(code, Some(0));
+    assert_eq!(events[1]["file"]["type"], "created");
+    assert!(events[1]["file"]["diff"]
+        .as_str()
+        .unwrap()
+        .contains("+world"));
+    assert_eq!(events[2]["file"]["type"], "deleted");
+    assert!(!dir.path().join("new.txt").exists());
+
+    let (code, _) = run_patch(dir.path(), &["--patch", "-"], Some(patch));
+    assert_eq!(code, Some(0));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("new.txt")).unwrap(),
+        "hello\nworld\n"
+    );
+    assert!(!dir.path().join("old.txt").exists());
+}
+
+#[test]
+fn test_patch_rejects_find_replace() {
+    let dir = setup();
+    let (code, _) = run_patch(dir.path(), &["--patch", "change.diff", "foo", "bar"], None);
+    assert_eq!(code, Some(1));
+}
+
+#[test]
+fn test_patch_renames_with_and_without_edits() {
+    let dir = setup();
+    fs::write(dir.path().join("notes.bin"), b"\xff\xfe raw").unwrap();
+    let patch = "\
+diff --git a/src/lib.rs b/src/main.rs
+similarity index 80%
+rename from src/lib.rs
+rename to src/main.rs
+--- a/src/lib.rs
++++ b/src/main.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    old();
++    new();
+ }
+diff --git a/notes.bin b/docs/notes.bin
+similarity index 100%
+rename from notes.bin
+rename to docs/notes.bin
+";
+
+    let (code, events) = run_patch(dir.path(), &["--patch", "-"], Some(patch));
+    assert_eq!(code, Some(0));
+    assert_eq!(events[1]["file"]["type"], "renamed");
+    assert_eq!(events[1]["file"]["from"], "src/lib.rs");
+    assert_eq!(events[2]["file"]["type"], "renamed");
+
+    assert!(!dir.path().join("src/lib.rs").exists());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/main.rs")).unwrap(),
+        "// header\nfn main() {\n    new();\n}\n"
+    );
+    assert!(!dir.path().join("notes.bin").exists());
+    assert_eq!(
+        fs::read(dir.path().join("docs/notes.bin")).unwrap(),
+        b"\xff\xfe raw"
+    );
+}
+
+#[test]
+fn test_patch_rejects_paths_outside_the_working_directory() {
+    let dir = tempdir().unwrap();
+    let work = dir.path().join("work");
+    fs::create_dir(&work).unwrap();
+    fs::write(dir.path().join("secret.txt"), "keep\n").unwrap();
+    let patch = "\
+--- a/../secret.txt
++++ b/../secret.txt
+@@ -1 +1 @@
+-keep
++gone
+";
+
+    let output = cargo_bin_cmd!("txed")
+        .current_dir(&work)
+        .args(["--patch", "-"])
+        .write_stdin(patch)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("outside the working directory"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("secret.txt")).unwrap(),
+        "keep\n"
+    );
+}
+
+#[test]
+fn test_patch_merges_sections_for_the_same_file() {
+    let dir = setup();
+    let patch = "\
+--- a/README.md
++++ b/README.md
+@@ -1,2 +1,2 @@
+-# Title
++# Heading
+ Body
+--- a/README.md
++++ b/README.md
+@@ -1,2 +1,3 @@
+ # Heading
+ Body
++Footer
+";
+
+    let (code, events) = run_patch(dir.path(), &["--patch", "-"], Some(patch));
+    assert_eq!(code, Some(0));
+    assert_eq!(events[2]["run_end"]["total_replacements"], 2);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("README.md")).unwrap(),
+        "# Heading\nBody\nFooter\n"
+    );
+}
@@ -58,3 +58,61 @@ fn test_range_start_unbounded() {
     let content = fs::read_to_string(&file_path).unwrap();
     assert_eq!(content, "foo\nfoo\nbar\nbar");
 }
+
+#[test]
+fn test_nth_selects_occurrences() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("test_nth.txt");
+
+    for (nth, expected) in [
+        ("2", "foo bar foo foo foo"),
+        ("-1", "foo foo foo foo bar"),
+        ("3:4", "foo foo bar bar foo"),
+        ("-2:", "foo foo foo bar bar"),
+        (":2", "bar bar foo foo foo"),
+    ] {
+        fs::write(&file_path, "foo foo foo foo foo").unwrap();
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+        cmd.arg("foo")
+            .arg("bar")
+            .arg("--nth")
+            .arg(nth)
+            .arg(file_path.to_str().unwrap())
+            .assert()
+            .success();
+
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), expected, "{}", nth);
+    }
+}
+
+#[test]
+fn test_nth_counts_within_range() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("test_nth_range.txt");
+    fs::write(&file_path, "foo\nfoo foo\nfoo foo\n").unwrap();
+
+    // The last match on line 2, not the last one in the file
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg("foo")
+        .arg("bar")
+        .args(["--range", "2", "--nth", "-1"])
+        .arg(file_path.to_str().unwrap())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "foo\nfoo bar\nfoo foo\n"
+    );
+}
+
+#[test]
+fn test_nth_zero_is_rejected() {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg("foo")
+        .arg("bar")
+        .args(["--nth", "0", "--stdin-text"])
+        .write_stdin("foo")
+        .assert()
+        .failure();
+}
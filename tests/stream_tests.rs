@@ -0,0 +1,203 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use predicates::prelude::*;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufWriter, Write};
+use tempfile::tempdir;
+
+fn file_event(stdout: &[u8]) -> Value {
+    let stdout = String::from_utf8(stdout.to_vec()).unwrap();
+    let line = stdout.lines().nth(1).unwrap();
+    serde_json::from_str::<Value>(line).unwrap()["file"].clone()
+}
+
+#[test]
+fn test_stream_matches_in_memory_result() {
+    let dir = tempdir().unwrap();
+    let streamed = dir.path().join("streamed.log");
+    let in_memory = dir.path().join("in_memory.log");
+    let content = "\u{feff}id=1 foo\r\nid=2 foo foo\r\nid=3 bar\r\nid=4 foo";
+    fs::write(&streamed, content).unwrap();
+    fs::write(&in_memory, content).unwrap();
+
+    for (path, stream) in [(&streamed, true), (&in_memory, false)] {
+        let mut cmd = cargo_bin_cmd!("txed");
+        if stream {
+            cmd.arg("--stream");
+        }
+        let output = cmd
+            .arg("--format=json")
+            .arg("--range=2:")
+            .arg("--limit=2")
+            .arg("foo")
+            .arg("baz")
+            .arg(path.to_str().unwrap())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        let event = file_event(&output.stdout);
+        assert_eq!(event["replacements"], 2);
+        assert_eq!(event["bom"], true);
+    }
+
+    assert_eq!(fs::read(&streamed).unwrap(), fs::read(&in_memory).unwrap());
+    assert_eq!(
+        fs::read_to_string(&streamed).unwrap(),
+        "\u{feff}id=1 foo\r\nid=2 baz baz\r\nid=3 bar\r\nid=4 foo"
+    );
+}
+
+#[test]
+fn test_stream_manifest_line_operations_on_large_file() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("dump.sql");
+    let lines = 200_000;
+    {
+        let mut writer = BufWriter::new(fs::File::create(&file).unwrap());
+        for i in 0..lines {
+            writeln!(writer, "INSERT INTO t VALUES ({}, 'secret');", i).unwrap();
+            if i % 2 == 0 {
+                writeln!(writer, "-- comment {}", i).unwrap();
+            }
+        }
+        writer.flush().unwrap();
+    }
+
+    let manifest = dir.path().join("manifest.json");
+    let manifest_json = json!({
+        "files": [file.to_str().unwrap()],
+        "stream": true,
+        "operations": [
+            { "type": "delete_lines", "find": "-- comment" },
+            { "type": "replace", "find": "'secret'", "with": "'redacted'" }
+        ]
+    });
+    fs::write(&manifest, manifest_json.to_string()).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("apply")
+        .arg("--manifest")
+        .arg(manifest.to_str().unwrap())
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        file_event(&output.stdout)["replacements"],
+        lines + lines / 2
+    );
+
+    let content = fs::read_to_string(&file).unwrap();
+    assert_eq!(content.lines().count(), lines);
+    assert!(!content.contains("secret"));
+    assert!(content.ends_with("INSERT INTO t VALUES (199999, 'redacted');\n"));
+}
+
+#[test]
+fn test_stream_dry_run_lists_changed_lines() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "keep\nold\nkeep\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    let output = cmd
+        .arg("--stream")
+        .arg("--dry-run")
+        .arg("--format=json")
+        .arg("old")
+        .arg("new")
+        .arg(file.to_str().unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let event = file_event(&output.stdout);
+    assert_eq!(event["modified"], true);
+    assert_eq!(event["diff"], "-old\n+new\n");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep\nold\nkeep\n");
+}
+
+#[test]
+fn test_stream_anchors_match_in_memory_result() {
+    let dir = tempdir().unwrap();
+    let content = "foo=1\nfoo=2\n";
+
+    // With multiline, ^ and $ are line anchors in both modes
+    let mut results = Vec::new();
+    for stream in [true, false] {
+        let file = dir.path().join(format!("multiline-{}.txt", stream));
+        fs::write(&file, content).unwrap();
+        let mut cmd = cargo_bin_cmd!("txed");
+        if stream {
+            cmd.arg("--stream");
+        }
+        cmd.arg("--regex")
+            .arg("--multiline")
+            .arg(r"^foo=(\d)$")
+            .arg("bar=$1")
+            .arg("--expand")
+            .arg(file.to_str().unwrap())
+            .assert()
+            .success();
+        results.push(fs::read_to_string(&file).unwrap());
+    }
+    assert_eq!(results, ["bar=1\nbar=2\n", "bar=1\nbar=2\n"]);
+
+    // Without it, ^ only matches at the start of the file, which a stream cannot tell apart
+    let file = dir.path().join("anchored.txt");
+    fs::write(&file, content).unwrap();
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--regex")
+        .arg("^foo")
+        .arg("bar")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "bar=1\nfoo=2\n");
+
+    fs::write(&file, content).unwrap();
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--stream")
+        .arg("--regex")
+        .arg("^foo")
+        .arg("bar")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("multiline"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), content);
+}
+
+#[test]
+fn test_stream_rejects_multi_line_operations() {
+    let dir = tempdir().unwrap();
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "a\nb\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--stream")
+        .arg("--regex")
+        .arg("--dot-matches-newline")
+        .arg("a.b")
+        .arg("x")
+        .arg(file.to_str().unwrap())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line-bounded"));
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--stream")
+        .arg("--stdin-text")
+        .arg("foo")
+        .arg("x")
+        .write_stdin("foo\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--stream only applies to file inputs",
+        ));
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a\nb\n");
+}
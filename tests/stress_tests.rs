@@ -164,3 +164,48 @@ fn test_rg_json_streaming() {
     assert!(new_content.contains("line 0: hello universe"));
     assert!(new_content.contains("line 9999: hello universe"));
 }
+
+#[test]
+fn test_large_file_regex_throughput() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("large_log.txt");
+
+    // ~16MB of log lines, every line matching once
+    let line = "2024-01-01 level=info request_id=abc123 status=200\n";
+    let lines = 16 * 1024 * 1024 / line.len();
+    {
+        let mut writer = BufWriter::new(File::create(&file_path).unwrap());
+        for _ in 0..lines {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+    let size = std::fs::metadata(&file_path).unwrap().len();
+
+    let mut cmd = cargo_bin_cmd!("txed");
+    cmd.arg("--regex")
+        .arg("--expand")
+        .arg(r"status=(\d+)")
+        .arg("code=$1")
+        .arg(file_path.to_str().unwrap())
+        .arg("--json");
+
+    let start = std::time::Instant::now();
+    let output = cmd.output().unwrap();
+    let duration = start.elapsed();
+    assert!(output.status.success());
+
+    let mb = size as f64 / (1024.0 * 1024.0);
+    println!(
+        "Regex replaced {:.1}MB in {:?} ({:.1} MB/s)",
+        mb,
+        duration,
+        mb / duration.as_secs_f64()
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("\"replacements\":{}", lines)));
+    let content = std::fs::read_to_string(&file_path).unwrap();
+    assert!(content.starts_with("2024-01-01 level=info request_id=abc123 code=200\n"));
+    assert!(!content.contains("status="));
+}
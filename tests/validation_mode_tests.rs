@@ -93,3 +93,164 @@ fn test_validation_mode_default_strict() {
             "Ambiguous capture group reference",
         ));
 }
+
+#[test]
+fn test_validation_warning_printed_once_per_run() {
+    let dir = tempdir().unwrap();
+    let first = dir.path().join("a.txt");
+    let second = dir.path().join("b.txt");
+    fs::write(&first, "hello").unwrap();
+    fs::write(&second, "world").unwrap();
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    let output = cmd
+        .arg(r"(\w+)")
+        .arg("$1bad")
+        .arg(first.to_str().unwrap())
+        .arg(second.to_str().unwrap())
+        .arg("--expand")
+        .arg("--regex")
+        .arg("--format=diff")
+        .arg("--validation-mode=warn")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("WARN: Ambiguous").count(), 1);
+    assert_eq!(fs::read_to_string(&second).unwrap(), "worldbad");
+}
+
+#[test]
+fn test_invalid_regex_fails_before_any_file_is_touched() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("untouched.txt");
+    fs::write(&file_path, "hello (").unwrap();
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg("(")
+        .arg("x")
+        .arg(file_path.to_str().unwrap())
+        .arg("--regex")
+        .arg("--format=diff")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid regex"));
+
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "hello (");
+}
+
+#[test]
+fn test_replacement_transforms() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("transform.txt");
+    fs::write(&file_path, "get_user_name set_item\n").unwrap();
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg(r"(?P<verb>get|set)_(\w+)")
+        .arg(r"${verb:upper}:${2:camel}:\U$2\E")
+        .arg(file_path.to_str().unwrap())
+        .arg("--expand")
+        .arg("--regex")
+        .arg("--validation-mode=strict")
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "GET:userName:USER_NAME SET:item:ITEM\n"
+    );
+}
+
+#[test]
+fn test_expand_escaped_backslashes_in_windows_path() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("paths.txt");
+    fs::write(&file_path, "home=alice\n").unwrap();
+
+    // `\\U` is an escaped backslash followed by `U`, not an uppercase span
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg(r"home=(\w+)")
+        .arg(r"home=C:\\Users\\$1")
+        .arg(file_path.to_str().unwrap())
+        .arg("--expand")
+        .arg("--regex")
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "home=C:\\Users\\alice\n"
+    );
+}
+
+#[test]
+fn test_validation_mode_strict_unknown_transform() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("transform.txt");
+    fs::write(&file_path, "hello world").unwrap();
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg(r"(\w+)")
+        .arg("${1:shout}")
+        .arg(file_path.to_str().unwrap())
+        .arg("--expand")
+        .arg("--regex")
+        .arg("--validation-mode=strict")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown transform `shout`"));
+
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "hello world");
+}
+
+#[test]
+fn test_reserved_template_variables() {
+    for stream in [false, true] {
+        let dir = tempdir().unwrap();
+        let files: Vec<_> = (0..6)
+            .map(|i| {
+                let path = dir.path().join(format!("f{}.txt", i));
+                fs::write(&path, "TAG\nx TAG\n").unwrap();
+                path
+            })
+            .collect();
+
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+        cmd.arg("TAG")
+            .arg("${__basename}:${__line}:${__col}#${__n}/${__global}")
+            .args(&files)
+            .arg("--expand");
+        if stream {
+            cmd.arg("--stream");
+        }
+        cmd.assert().success();
+
+        // Run-wide ordinals follow the order the inputs were given in
+        for (i, path) in files.iter().enumerate() {
+            assert_eq!(
+                fs::read_to_string(path).unwrap(),
+                format!(
+                    "f{i}.txt:1:1#1/{}\nx f{i}.txt:2:3#2/{}\n",
+                    2 * i + 1,
+                    2 * i + 2
+                )
+            );
+        }
+    }
+}
+
+#[test]
+fn test_validation_mode_strict_unknown_variable() {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_txed"));
+    cmd.arg("x")
+        .arg("${__count}")
+        .arg("--stdin-text")
+        .arg("--expand")
+        .write_stdin("x")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Unknown template variable `__count`",
+        ));
+}
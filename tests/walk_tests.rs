@@ -0,0 +1,82 @@
+#![cfg(feature = "walk")]
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use tempfile::tempdir;
+
+fn setup() -> tempfile::TempDir {
+    let dir = tempdir().unwrap();
+    let tree = dir.path().join("tree");
+    fs::create_dir_all(tree.join("nested")).unwrap();
+    fs::write(tree.join(".gitignore"), "ignored.txt\n").unwrap();
+    fs::write(tree.join("top.txt"), "foo\n").unwrap();
+    fs::write(tree.join("ignored.txt"), "foo\n").unwrap();
+    fs::write(tree.join(".hidden.txt"), "foo\n").unwrap();
+    fs::write(tree.join("skip.log"), "foo\n").unwrap();
+    fs::write(tree.join("nested/deep.txt"), "foo\n").unwrap();
+    dir
+}
+
+fn read(dir: &tempfile::TempDir, name: &str) -> String {
+    fs::read_to_string(dir.path().join("tree").join(name)).unwrap()
+}
+
+#[test]
+fn test_walk_honors_gitignore_hidden_and_globs() {
+    let dir = setup();
+
+    let output = cargo_bin_cmd!("txed")
+        .current_dir(dir.path())
+        .args(["--walk", "tree", "--glob-exclude", "*.log", "--format=json"])
+        .args(["foo", "bar"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let first: Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    assert_eq!(first["run_start"]["input_mode"], "walk");
+
+    assert_eq!(read(&dir, "top.txt"), "bar\n");
+    assert_eq!(read(&dir, "nested/deep.txt"), "bar\n");
+    assert_eq!(read(&dir, "ignored.txt"), "foo\n");
+    assert_eq!(read(&dir, ".hidden.txt"), "foo\n");
+    assert_eq!(read(&dir, "skip.log"), "foo\n");
+}
+
+#[test]
+fn test_walk_hidden_no_ignore_and_max_depth() {
+    let dir = setup();
+
+    cargo_bin_cmd!("txed")
+        .current_dir(dir.path())
+        .args([
+            "--walk",
+            "tree",
+            "--hidden",
+            "--no-ignore",
+            "--max-depth",
+            "1",
+        ])
+        .args(["foo", "bar"])
+        .assert()
+        .success();
+
+    assert_eq!(read(&dir, "ignored.txt"), "bar\n");
+    assert_eq!(read(&dir, ".hidden.txt"), "bar\n");
+    assert_eq!(read(&dir, "nested/deep.txt"), "foo\n");
+}
+
+#[test]
+fn test_walk_rejects_file_arguments() {
+    let dir = setup();
+
+    cargo_bin_cmd!("txed")
+        .current_dir(dir.path())
+        .args(["--walk", "tree", "foo", "bar", "tree/top.txt"])
+        .assert()
+        .failure();
+
+    assert_eq!(read(&dir, "top.txt"), "foo\n");
+}